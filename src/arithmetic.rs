@@ -1,12 +1,10 @@
 use crate::registers::*;
 use crate::memory::*;
-use crate::mode::*;
+use crate::instruction::*;
+use crate::operand::*;
+use crate::interrupt::*;
 
-const ARITHMETIC_INSTRUCTION_ENCODINGS: &'static [&str] = &[
-    "add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"
-];
-
-fn set_bit(mut x: u16, bit_flag: u16, value: bool) -> u16 {
+pub fn set_bit(mut x: u16, bit_flag: u16, value: bool) -> u16 {
     if value {
         x |= bit_flag;
     } else {
@@ -16,765 +14,683 @@ fn set_bit(mut x: u16, bit_flag: u16, value: bool) -> u16 {
     return x;
 }
 
-fn update_flags_register_16_bit(mut flags_register: u16, result: u16) -> u16 {
-    let is_zero: bool = result == 0;
+// Sets the zero, sign and parity flags which every arithmetic and logic operation derives the same way
+pub fn update_flags_register(mut flags_register: u16, result: u16, width: Width) -> u16 {
+    let is_zero: bool = result & width.mask() == 0;
     flags_register = set_bit(flags_register, ZF_FLAG_BIT, is_zero);
 
-    let is_signed: bool = result & 0x8000 != 0;
+    let is_signed: bool = result & width.sign_bit() != 0;
     flags_register = set_bit(flags_register, SF_FLAG_BIT, is_signed);
 
+    let has_even_parity: bool = (result & 0x00FF).count_ones() & 1 == 0;
+    flags_register = set_bit(flags_register, PF_FLAG_BIT, has_even_parity);
+
     return flags_register;
 }
 
-fn update_flags_register_8_bit(mut flags_register: u16, result: u8) -> u16 {
-    let is_zero: bool = result == 0;
-    flags_register = set_bit(flags_register, ZF_FLAG_BIT, is_zero);
+fn update_flags_register_add(mut flags_register: u16, x: u16, y: u16, full_result: u32, width: Width) -> u16 {
+    let result: u16 = full_result as u16 & width.mask();
+    flags_register = update_flags_register(flags_register, result, width);
 
-    let is_signed: bool = result & 0x80 != 0;
-    flags_register = set_bit(flags_register, SF_FLAG_BIT, is_signed);
+    let carry: bool = full_result > width.mask() as u32;
+    flags_register = set_bit(flags_register, CF_FLAG_BIT, carry);
+
+    let auxiliary_carry: bool = (x ^ y ^ result) & 0x0010 != 0;
+    flags_register = set_bit(flags_register, AF_FLAG_BIT, auxiliary_carry);
+
+    // Overflow when both operands have the same sign and the result's sign differs
+    let overflow: bool = (x ^ result) & (y ^ result) & width.sign_bit() != 0;
+    flags_register = set_bit(flags_register, OF_FLAG_BIT, overflow);
 
     return flags_register;
 }
 
-fn add_op_16_bit(x: u16, y: u16, flags_register: &mut u16) -> u16 {
-    let result: u16 = x.wrapping_add(y);
-    *flags_register = update_flags_register_16_bit(*flags_register, result);
-    return result;
+fn update_flags_register_sub(mut flags_register: u16, x: u16, y: u16, full_result: u32, width: Width) -> u16 {
+    let result: u16 = full_result as u16 & width.mask();
+    flags_register = update_flags_register(flags_register, result, width);
+
+    // Subtraction wraps below zero when a borrow is needed
+    let borrow: bool = full_result > width.mask() as u32;
+    flags_register = set_bit(flags_register, CF_FLAG_BIT, borrow);
+
+    let auxiliary_borrow: bool = (x ^ y ^ result) & 0x0010 != 0;
+    flags_register = set_bit(flags_register, AF_FLAG_BIT, auxiliary_borrow);
+
+    // Overflow when the operands have different signs and the result's sign differs from x
+    let overflow: bool = (x ^ y) & (x ^ result) & width.sign_bit() != 0;
+    flags_register = set_bit(flags_register, OF_FLAG_BIT, overflow);
+
+    return flags_register;
 }
 
-fn or_op_16_bit(x: u16, y: u16, flags_register: &mut u16) -> u16 {
+// Logic operations always clear carry and overflow; auxiliary carry is undefined and cleared here
+fn update_flags_register_logic(mut flags_register: u16, result: u16, width: Width) -> u16 {
+    flags_register = update_flags_register(flags_register, result, width);
+    flags_register &= !(CF_FLAG_BIT | AF_FLAG_BIT | OF_FLAG_BIT);
+
+    return flags_register;
+}
+
+fn add_op(x: u16, y: u16, width: Width, flags_register: &mut u16) -> u16 {
+    let full_result: u32 = x as u32 + y as u32;
+    *flags_register = update_flags_register_add(*flags_register, x, y, full_result, width);
+    return full_result as u16 & width.mask();
+}
+
+// Adds in the carry flag as well, for chaining additions across several words
+fn adc_op(x: u16, y: u16, width: Width, flags_register: &mut u16) -> u16 {
+    let carry_in: u32 = (*flags_register & CF_FLAG_BIT) as u32;
+    let full_result: u32 = x as u32 + y as u32 + carry_in;
+    *flags_register = update_flags_register_add(*flags_register, x, y, full_result, width);
+    return full_result as u16 & width.mask();
+}
+
+// Subtracts the carry flag as a borrow from a previous subtraction
+fn sbb_op(x: u16, y: u16, width: Width, flags_register: &mut u16) -> u16 {
+    let borrow_in: u32 = (*flags_register & CF_FLAG_BIT) as u32;
+    let full_result: u32 = (x as u32).wrapping_sub(y as u32).wrapping_sub(borrow_in);
+    *flags_register = update_flags_register_sub(*flags_register, x, y, full_result, width);
+    return full_result as u16 & width.mask();
+}
+
+fn or_op(x: u16, y: u16, width: Width, flags_register: &mut u16) -> u16 {
     let result: u16 = x | y;
-    *flags_register = update_flags_register_16_bit(*flags_register, result);
+    *flags_register = update_flags_register_logic(*flags_register, result, width);
     return result;
 }
 
-fn and_op_16_bit(x: u16, y: u16, flags_register: &mut u16) -> u16 {
+fn and_op(x: u16, y: u16, width: Width, flags_register: &mut u16) -> u16 {
     let result: u16 = x & y;
-    *flags_register = update_flags_register_16_bit(*flags_register, result);
+    *flags_register = update_flags_register_logic(*flags_register, result, width);
     return result;
 }
 
-fn sub_op_16_bit(x: u16, y: u16, flags_register: &mut u16) -> u16 {
-    let result: u16 = x.wrapping_sub(y);
-    *flags_register = update_flags_register_16_bit(*flags_register, result);
-    return result;
+fn sub_op(x: u16, y: u16, width: Width, flags_register: &mut u16) -> u16 {
+    let full_result: u32 = (x as u32).wrapping_sub(y as u32);
+    *flags_register = update_flags_register_sub(*flags_register, x, y, full_result, width);
+    return full_result as u16 & width.mask();
 }
 
-fn xor_op_16_bit(x: u16, y: u16, flags_register: &mut u16) -> u16 {
+fn xor_op(x: u16, y: u16, width: Width, flags_register: &mut u16) -> u16 {
     let result: u16 = x ^ y;
-    *flags_register = update_flags_register_16_bit(*flags_register, result);
+    *flags_register = update_flags_register_logic(*flags_register, result, width);
     return result;
 }
 
-fn cmp_op_16_bit(x: u16, y: u16, flags_register: &mut u16) {
-    let result: u16 = x.wrapping_sub(y);
-    *flags_register = update_flags_register_16_bit(*flags_register, result);
+fn cmp_op(x: u16, y: u16, width: Width, flags_register: &mut u16) {
+    sub_op(x, y, width, flags_register);
 }
 
-fn arithmetic_op_with_reg_16_bit(registers: &mut Registers, field_index: u8, instruction_index: u8, immediate: u16) {
-    let field_value: u16 = get_16_bit_register(registers, field_index);
-
-    match instruction_index {
-        0 => {
-            let add_result: u16 = add_op_16_bit(field_value, immediate, &mut registers.flags);
-            set_16_bit_register(registers, field_index, add_result);
-        },
-        1 => {
-            let or_result: u16 = or_op_16_bit(field_value, immediate, &mut registers.flags);
-            set_16_bit_register(registers, field_index, or_result);
-        },
-        2 => {
-            debug_assert!(false);
-        },
-        3 => {
-            debug_assert!(false);
-        },
-        4 => {
-            let and_result: u16 = and_op_16_bit(field_value, immediate, &mut registers.flags);
-            set_16_bit_register(registers, field_index, and_result);
-        },
-        5 => {
-            let sub_result: u16 = sub_op_16_bit(field_value, immediate, &mut registers.flags);
-            set_16_bit_register(registers, field_index, sub_result);
-        },
-        6 => {
-            let xor_result: u16 = xor_op_16_bit(field_value, immediate, &mut registers.flags);
-            set_16_bit_register(registers, field_index, xor_result);
-        },
-        7 => {
-            cmp_op_16_bit(field_value, immediate, &mut registers.flags);
+// Operands are held in the low bits for byte operations. Returns None when the operation only
+// updates flags.
+pub fn arithmetic_op(operation: Operation, x: u16, y: u16, width: Width, flags_register: &mut u16) -> Option<u16> {
+    match operation {
+        Operation::Add => { return Some(add_op(x, y, width, flags_register)); },
+        Operation::Or => { return Some(or_op(x, y, width, flags_register)); },
+        Operation::Adc => { return Some(adc_op(x, y, width, flags_register)); },
+        Operation::Sbb => { return Some(sbb_op(x, y, width, flags_register)); },
+        Operation::And => { return Some(and_op(x, y, width, flags_register)); },
+        Operation::Sub => { return Some(sub_op(x, y, width, flags_register)); },
+        Operation::Xor => { return Some(xor_op(x, y, width, flags_register)); },
+        Operation::Cmp => {
+            cmp_op(x, y, width, flags_register);
+            return None;
+        },
+        Operation::Test => {
+            and_op(x, y, width, flags_register);
+            return None;
         },
         _ => {
-            debug_assert!(false);
+            unreachable!("{:?} is not an arithmetic operation", operation);
         }
     }
 }
 
-fn arithmetic_op_with_mem_16_bit(registers: &mut Registers, memory: &mut Memory, address: u16, instruction_index: u8, immediate: u16) {
-    let word: u16 = load_word(memory, address);
+pub fn execute_arithmetic(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let destination: Operand = instruction.destination.expect("arithmetic instructions have a destination");
+    let source: Operand = instruction.source.expect("arithmetic instructions have a source");
 
-    match instruction_index {
-        0 => {
-            let add_result: u16 = add_op_16_bit(word, immediate, &mut registers.flags);
-            store_word(memory, address, add_result);
-        },
-        1 => {
-            let or_result: u16 = or_op_16_bit(word, immediate, &mut registers.flags);
-            store_word(memory, address, or_result);
-        },
-        2 => {
-            debug_assert!(false);
-        },
-        3 => {
-            debug_assert!(false);
-        },
-        4 => {
-            let and_result: u16 = and_op_16_bit(word, immediate, &mut registers.flags);
-            store_word(memory, address, and_result);
-        },
-        5 => {
-            let sub_result: u16 = sub_op_16_bit(word, immediate, &mut registers.flags);
-            store_word(memory, address, sub_result);
-        },
-        6 => {
-            let xor_result: u16 = xor_op_16_bit(word, immediate, &mut registers.flags);
-            store_word(memory, address, xor_result);
-        },
-        7 => {
-            cmp_op_16_bit(word, immediate, &mut registers.flags);
-        },
-        _ => {
-            debug_assert!(false);
-        }
+    let x: u16 = read_operand(registers, memory, destination, instruction.width);
+    let y: u16 = read_operand(registers, memory, source, instruction.width);
+    if let Some(result) = arithmetic_op(instruction.operation, x, y, instruction.width, &mut registers.flags) {
+        write_operand(registers, memory, destination, instruction.width, result);
     }
 }
 
-fn add_op_8_bit(x: u8, y: u8, flags_register: &mut u16) -> u8 {
-    let result: u8 = x.wrapping_add(y);
-    *flags_register = update_flags_register_8_bit(*flags_register, result);
-    return result;
-}
+// Like adding or subtracting one except the carry flag is left alone
+pub fn execute_inc_dec(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let destination: Operand = instruction.destination.expect("inc and dec have a destination");
 
-fn or_op_8_bit(x: u8, y: u8, flags_register: &mut u16) -> u8 {
-    let result: u8 = x | y;
-    *flags_register = update_flags_register_8_bit(*flags_register, result);
-    return result;
-}
+    let x: u16 = read_operand(registers, memory, destination, instruction.width);
+    let mut flags_register: u16 = registers.flags;
+    let result: u16 = if instruction.operation == Operation::Inc {
+        add_op(x, 1, instruction.width, &mut flags_register)
+    } else {
+        sub_op(x, 1, instruction.width, &mut flags_register)
+    };
+    registers.flags = set_bit(flags_register, CF_FLAG_BIT, registers.flags & CF_FLAG_BIT != 0);
 
-fn and_op_8_bit(x: u8, y: u8, flags_register: &mut u16) -> u8 {
-    let result: u8 = x & y;
-    *flags_register = update_flags_register_8_bit(*flags_register, result);
-    return result;
+    write_operand(registers, memory, destination, instruction.width, result);
 }
 
-fn sub_op_8_bit(x: u8, y: u8, flags_register: &mut u16) -> u8 {
-    let result: u8 = x.wrapping_sub(y);
-    *flags_register = update_flags_register_8_bit(*flags_register, result);
-    return result;
+pub fn execute_not(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let destination: Operand = instruction.destination.expect("not has a destination");
+
+    let x: u16 = read_operand(registers, memory, destination, instruction.width);
+    write_operand(registers, memory, destination, instruction.width, !x & instruction.width.mask());
 }
 
-fn xor_op_8_bit(x: u8, y: u8, flags_register: &mut u16) -> u8 {
-    let result: u8 = x ^ y;
-    *flags_register = update_flags_register_8_bit(*flags_register, result);
-    return result;
+// Flags are as for subtracting from zero so CF is set unless the operand was zero
+pub fn execute_neg(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let destination: Operand = instruction.destination.expect("neg has a destination");
+
+    let x: u16 = read_operand(registers, memory, destination, instruction.width);
+    let result: u16 = sub_op(0, x, instruction.width, &mut registers.flags);
+    write_operand(registers, memory, destination, instruction.width, result);
 }
 
-fn cmp_op_8_bit(x: u8, y: u8, flags_register: &mut u16) {
-    let result: u8 = x.wrapping_sub(y);
-    *flags_register = update_flags_register_8_bit(*flags_register, result);
+// The product goes in AX for bytes and DX:AX for words. CF and OF are set when the top half is
+// needed to hold it; the other flags are undefined and left alone.
+pub fn execute_multiply(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let source: Operand = instruction.source.expect("multiplication has a source");
+    let y: u16 = read_operand(registers, memory, source, instruction.width);
+    let signed: bool = instruction.operation == Operation::Imul;
+
+    let top_half_needed: bool = match instruction.width {
+        Width::Byte => {
+            let al: u8 = get_low_byte(registers.ax);
+            if signed {
+                let product: i16 = al as i8 as i16 * y as u8 as i8 as i16;
+                registers.ax = product as u16;
+                product != product as i8 as i16
+            } else {
+                let product: u16 = al as u16 * y;
+                registers.ax = product;
+                product > 0x00FF
+            }
+        },
+        Width::Word => {
+            let product: u32 = if signed {
+                (registers.ax as i16 as i32 * y as i16 as i32) as u32
+            } else {
+                registers.ax as u32 * y as u32
+            };
+            registers.ax = product as u16;
+            registers.dx = (product >> 16) as u16;
+
+            if signed {
+                product as i32 != product as i16 as i32
+            } else {
+                registers.dx != 0
+            }
+        }
+    };
+
+    registers.flags = set_bit(registers.flags, CF_FLAG_BIT, top_half_needed);
+    registers.flags = set_bit(registers.flags, OF_FLAG_BIT, top_half_needed);
 }
 
-fn arithmetic_op_with_reg_8_bit(registers: &mut Registers, field_index: u8, instruction_index: u8, immediate: u8) {
-    let field_value: u8 = get_8_bit_register(registers, field_index);
+// Divides AX by a byte into AL remainder AH, or DX:AX by a word into AX remainder DX. Returns None
+// when the divisor is zero or the quotient doesn't fit. Like the 8086 a signed quotient can't be
+// the most negative value.
+fn divide(dividend: u32, divisor: u16, width: Width, signed: bool) -> Option<(u16, u16)> {
+    if divisor == 0 {
+        return None;
+    }
 
-    match instruction_index {
-        0 => {
-            let add_result: u8 = add_op_8_bit(field_value, immediate, &mut registers.flags);
-            set_8_bit_register(registers, field_index, add_result);
-        },
-        1 => {
-            let or_result: u8 = or_op_8_bit(field_value, immediate, &mut registers.flags);
-            set_8_bit_register(registers, field_index, or_result);
-        },
-        2 => {
-            debug_assert!(false);
-        },
-        3 => {
-            debug_assert!(false);
-        },
-        4 => {
-            let and_result: u8 = and_op_8_bit(field_value, immediate, &mut registers.flags);
-            set_8_bit_register(registers, field_index, and_result);
-        },
-        5 => {
-            let sub_result: u8 = sub_op_8_bit(field_value, immediate, &mut registers.flags);
-            set_8_bit_register(registers, field_index, sub_result);
-        },
-        6 => {
-            let xor_result: u8 = xor_op_8_bit(field_value, immediate, &mut registers.flags);
-            set_8_bit_register(registers, field_index, xor_result);
-        },
-        7 => {
-            cmp_op_8_bit(field_value, immediate, &mut registers.flags);
-        },
-        _ => {
-            debug_assert!(false);
+    let (quotient, remainder): (i64, i64) = match (width, signed) {
+        (_, false) => ((dividend / divisor as u32) as i64, (dividend % divisor as u32) as i64),
+        (Width::Byte, true) => {
+            let x: i64 = dividend as u16 as i16 as i64;
+            let y: i64 = divisor as u8 as i8 as i64;
+            (x / y, x % y)
+        },
+        (Width::Word, true) => {
+            let x: i64 = dividend as i32 as i64;
+            let y: i64 = divisor as i16 as i64;
+            (x / y, x % y)
         }
+    };
+
+    let limit: i64 = width.sign_bit() as i64;
+    let fits: bool = if signed {
+        quotient > -limit && quotient < limit
+    } else {
+        quotient <= width.mask() as i64
+    };
+    if !fits {
+        return None;
     }
+
+    return Some((quotient as u16 & width.mask(), remainder as u16 & width.mask()));
 }
 
-fn arithmetic_op_with_mem_8_bit(registers: &mut Registers, memory: &mut Memory, address: u16, instruction_index: u8, immediate: u8) {
-    let byte: u8 = load_byte(memory, address);
+// A divide error raises interrupt 0 with the registers untouched. Flags are undefined and left alone.
+pub fn execute_divide(registers: &mut Registers, memory: &mut Memory, hooks: &mut InterruptHooks, instruction: &Instruction) {
+    let source: Operand = instruction.source.expect("division has a source");
+    let divisor: u16 = read_operand(registers, memory, source, instruction.width);
+    let signed: bool = instruction.operation == Operation::Idiv;
 
-    match instruction_index {
-        0 => {
-            let add_result: u8 = add_op_8_bit(byte, immediate, &mut registers.flags);
-            store_byte(memory, address, add_result);
-        },
-        1 => {
-            let or_result: u8 = or_op_8_bit(byte, immediate, &mut registers.flags);
-            store_byte(memory, address, or_result);
-        },
-        2 => {
-            debug_assert!(false);
-        },
-        3 => {
-            debug_assert!(false);
-        },
-        4 => {
-            let and_result: u8 = and_op_8_bit(byte, immediate, &mut registers.flags);
-            store_byte(memory, address, and_result);
-        },
-        5 => {
-            let sub_result: u8 = sub_op_8_bit(byte, immediate, &mut registers.flags);
-            store_byte(memory, address, sub_result);
-        },
-        6 => {
-            let xor_result: u8 = xor_op_8_bit(byte, immediate, &mut registers.flags);
-            store_byte(memory, address, xor_result);
+    let dividend: u32 = match instruction.width {
+        Width::Byte => registers.ax as u32,
+        Width::Word => ((registers.dx as u32) << 16) + registers.ax as u32
+    };
+
+    match (divide(dividend, divisor, instruction.width, signed), instruction.width) {
+        (Some((quotient, remainder)), Width::Byte) => {
+            registers.ax = (remainder << 8) + quotient;
         },
-        7 => {
-            cmp_op_8_bit(byte, immediate, &mut registers.flags);
+        (Some((quotient, remainder)), Width::Word) => {
+            registers.ax = quotient;
+            registers.dx = remainder;
         },
-        _ => {
-            debug_assert!(false);
+        (None, _) => {
+            dispatch_interrupt(registers, memory, hooks, DIVIDE_ERROR_VECTOR);
         }
     }
 }
 
-pub fn arithmetic_mem_reg_with_reg_to_either_16_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-
-    let instruction_index: u8 = (byte & 0x38) >> 3;
-    let d_bit: u8 = (byte & 0x02) >> 1;
-    let w_bit: u8 = byte & 0x01;
-    debug_assert!(w_bit == 1);
-
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-
-    let mod_field: u8 = (byte & 0xC0) >> 6;
-    let reg_field: u8 = (byte & 0x38) >> 3;
-    let rm_field: u8 = byte & 0x07;
-
-    match mod_field {
-        MODE_MEM_NO_DISP => {
-            if rm_field == 6 {
-                let address: u16 = grab_instruction_word(memory, &mut registers.ip);
-                if d_bit == 1 {
-                    let immediate: u16 = load_word(memory, address);
-                    arithmetic_op_with_reg_16_bit(registers, reg_field, instruction_index, immediate);
-
-                    let field: &str = REG_FIELD_ENCODINGS_16_BIT[reg_field as usize];
-                    let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                    println!("{} {}, [{}]", instruction, field, address);
-                } else {
-                    let immediate: u16 = get_16_bit_register(registers, reg_field);
-                    arithmetic_op_with_mem_16_bit(registers, memory, address, instruction_index, immediate);
-
-                    let field: &str = REG_FIELD_ENCODINGS_16_BIT[reg_field as usize];
-                    let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                    println!("{} [{}], {}", instruction, address, field);
-                }
-            } else {
-                let address: u16 = calculate_reg_expression(registers, rm_field);
-                if d_bit == 1 {
-                    let immediate: u16 = load_word(memory, address);
-                    arithmetic_op_with_reg_16_bit(registers, reg_field, instruction_index, immediate);
-
-                    let field: &str = REG_FIELD_ENCODINGS_16_BIT[reg_field as usize];
-                    let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                    let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                    println!("{} {}, [{}]", instruction, field, expression);
-                } else {
-                    let immediate: u16 = get_16_bit_register(registers, reg_field);
-                    arithmetic_op_with_mem_16_bit(registers, memory, address, instruction_index, immediate);
-
-                    let field: &str = REG_FIELD_ENCODINGS_16_BIT[reg_field as usize];
-                    let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                    let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                    println!("{} [{}], {}", instruction, expression, field);
-                }
-            }
-        },
-        MODE_MEM_8_BIT_DISP => {
-            let displacement: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            if d_bit == 1 {
-                let immediate: u16 = load_word(memory, address);
-                arithmetic_op_with_reg_16_bit(registers, reg_field, instruction_index, immediate);
-
-                let sign: char = if displacement > 0 { '+' } else { '-' };
-                let field: &str = REG_FIELD_ENCODINGS_16_BIT[reg_field as usize];
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                if displacement != 0 {
-                    println!("{} {}, [{} {} {}]", instruction, field, expression, sign, displacement.abs());
-                } else {
-                    println!("{} {}, [{}]", instruction, field, expression);
-                }
-            } else {
-                let immediate: u16 = get_16_bit_register(registers, reg_field);
-                arithmetic_op_with_mem_16_bit(registers, memory, address, instruction_index, immediate);
-
-                let sign: char = if displacement > 0 { '+' } else { '-' };
-                let field: &str = REG_FIELD_ENCODINGS_16_BIT[reg_field as usize];
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                if displacement != 0 {
-                    println!("{} [{} {} {}], {}", instruction, expression, sign, displacement.abs(), field);
-                } else {
-                    println!("{} [{}], {}", instruction, expression, field);
-                }
-            }
-        },
-        MODE_MEM_16_BIT_DISP => {
-            let displacement: i16 = grab_instruction_word(memory, &mut registers.ip) as i16;
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            if d_bit == 1 {
-                let immediate: u16 = load_word(memory, address);
-                arithmetic_op_with_reg_16_bit(registers, reg_field, instruction_index, immediate);
-
-                let sign: char = if displacement > 0 { '+' } else { '-' };
-                let field: &str = REG_FIELD_ENCODINGS_16_BIT[reg_field as usize];
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                if displacement != 0 {
-                    println!("{} {}, [{} {} {}]", instruction, field, expression, sign, displacement.abs());
-                } else {
-                    println!("{} {}, [{}]", instruction, field, expression);
-                }
-            } else {
-                let immediate: u16 = get_16_bit_register(registers, reg_field);
-                arithmetic_op_with_mem_16_bit(registers, memory, address, instruction_index, immediate);
-
-                let sign: char = if displacement > 0 { '+' } else { '-' };
-                let field: &str = REG_FIELD_ENCODINGS_16_BIT[reg_field as usize];
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                if displacement != 0 {
-                    println!("{} [{} {} {}], {}", instruction, expression, sign, displacement.abs(), field);
-                } else {
-                    println!("{} [{}], {}", instruction, expression, field);
-                }
-            }
-        },
-        MODE_REG => {
-            let (source_index, destination_index): (u8, u8) = if d_bit == 1 {
-                (rm_field, reg_field)
-            } else {
-                (reg_field, rm_field)
-            };
+// Corrects AL after adding or subtracting two packed BCD bytes. OF is undefined and left alone.
+pub fn execute_decimal_adjust(registers: &mut Registers, instruction: &Instruction) {
+    let original_al: u8 = get_low_byte(registers.ax);
+    let original_carry: bool = registers.flags & CF_FLAG_BIT != 0;
+    let subtract: bool = instruction.operation == Operation::Das;
+
+    let mut al: u8 = original_al;
+    let mut carry: bool = false;
+    let low_digit_overflow: bool = al & 0x0F > 9 || registers.flags & AF_FLAG_BIT != 0;
+    if low_digit_overflow {
+        let (adjusted, digit_carry): (u8, bool) = if subtract { al.overflowing_sub(0x06) } else { al.overflowing_add(0x06) };
+        al = adjusted;
+        carry = original_carry || digit_carry;
+    }
 
-            let immediate: u16 = get_16_bit_register(registers, source_index);
-            arithmetic_op_with_reg_16_bit(registers, destination_index, instruction_index, immediate);
+    if original_al > 0x99 || original_carry {
+        al = if subtract { al.wrapping_sub(0x60) } else { al.wrapping_add(0x60) };
+        carry = true;
+    }
 
-            let source: &str = REG_FIELD_ENCODINGS_16_BIT[source_index as usize];
-            let destination: &str = REG_FIELD_ENCODINGS_16_BIT[destination_index as usize];
-            let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-            println!("{} {}, {}", instruction, destination, source);
-        },
-        _ => {
-            debug_assert!(false);
+    registers.ax = set_low_byte(registers.ax, al);
+    registers.flags = set_bit(registers.flags, AF_FLAG_BIT, low_digit_overflow);
+    registers.flags = set_bit(registers.flags, CF_FLAG_BIT, carry);
+    registers.flags = update_flags_register(registers.flags, al as u16, Width::Byte);
+}
+
+// Corrects AX after adding or subtracting two unpacked BCD digits in AL, carrying into AH. Only AF
+// and CF are defined afterwards.
+pub fn execute_ascii_adjust(registers: &mut Registers, instruction: &Instruction) {
+    let mut al: u8 = get_low_byte(registers.ax);
+    let mut ah: u8 = get_high_byte(registers.ax);
+
+    let adjust: bool = al & 0x0F > 9 || registers.flags & AF_FLAG_BIT != 0;
+    if adjust {
+        if instruction.operation == Operation::Aaa {
+            al = al.wrapping_add(0x06);
+            ah = ah.wrapping_add(1);
+        } else {
+            al = al.wrapping_sub(0x06);
+            ah = ah.wrapping_sub(1);
         }
     }
-}
 
-pub fn arithmetic_mem_reg_with_reg_to_either_8_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-
-    let instruction_index: u8 = (byte & 0x38) >> 3;
-    let d_bit: u8 = (byte & 0x02) >> 1;
-    let w_bit: u8 = byte & 0x01;
-    debug_assert!(w_bit == 0);
-
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-
-    let mod_field: u8 = (byte & 0xC0) >> 6;
-    let reg_field: u8 = (byte & 0x38) >> 3;
-    let rm_field: u8 = byte & 0x07;
-
-    match mod_field {
-        MODE_MEM_NO_DISP => {
-            if rm_field == 6 {
-                let address: u16 = grab_instruction_word(memory, &mut registers.ip);
-                if d_bit == 1 {
-                    let immediate: u8 = load_byte(memory, address);
-                    arithmetic_op_with_reg_8_bit(registers, reg_field, instruction_index, immediate);
-
-                    let field: &str = REG_FIELD_ENCODINGS_8_BIT[reg_field as usize];
-                    let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                    println!("{} {}, [{}]", instruction, field, address);
-                } else {
-                    let immediate: u8 = get_8_bit_register(registers, reg_field);
-                    arithmetic_op_with_mem_8_bit(registers, memory, address, instruction_index, immediate);
-
-                    let field: &str = REG_FIELD_ENCODINGS_8_BIT[reg_field as usize];
-                    let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                    println!("{} [{}], {}", instruction, address, field);
-                }
-            } else {
-                let address: u16 = calculate_reg_expression(registers, rm_field);
-                if d_bit == 1 {
-                    let immediate: u8 = load_byte(memory, address);
-                    arithmetic_op_with_reg_8_bit(registers, reg_field, instruction_index, immediate);
-
-                    let field: &str = REG_FIELD_ENCODINGS_8_BIT[reg_field as usize];
-                    let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                    let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                    println!("{} {}, [{}]", instruction, field, expression);
-                } else {
-                    let immediate: u8 = get_8_bit_register(registers, reg_field);
-                    arithmetic_op_with_mem_8_bit(registers, memory, address, instruction_index, immediate);
-
-                    let field: &str = REG_FIELD_ENCODINGS_8_BIT[reg_field as usize];
-                    let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                    let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                    println!("{} [{}], {}", instruction, expression, field);
-                }
-            }
-        },
-        MODE_MEM_8_BIT_DISP => {
-            let displacement: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            if d_bit == 1 {
-                let immediate: u8 = load_byte(memory, address);
-                arithmetic_op_with_reg_8_bit(registers, reg_field, instruction_index, immediate);
-
-                let sign: char = if displacement > 0 { '+' } else { '-' };
-                let field: &str = REG_FIELD_ENCODINGS_8_BIT[reg_field as usize];
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                if displacement != 0 {
-                    println!("{} {}, [{} {} {}]", instruction, field, expression, sign, displacement.abs());
-                } else {
-                    println!("{} {}, [{}]", instruction, field, expression);
-                }
-            } else {
-                let immediate: u8 = get_8_bit_register(registers, reg_field);
-                arithmetic_op_with_mem_8_bit(registers, memory, address, instruction_index, immediate);
-
-                let sign: char = if displacement > 0 { '+' } else { '-' };
-                let field: &str = REG_FIELD_ENCODINGS_8_BIT[reg_field as usize];
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                if displacement != 0 {
-                    println!("{} [{} {} {}], {}", instruction, expression, sign, displacement.abs(), field);
-                } else {
-                    println!("{} [{}], {}", instruction, expression, field);
-                }
-            }
-        },
-        MODE_MEM_16_BIT_DISP => {
-            let displacement: i16 = grab_instruction_word(memory, &mut registers.ip) as i16;
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            if d_bit == 1 {
-                let immediate: u8 = load_byte(memory, address);
-                arithmetic_op_with_reg_8_bit(registers, reg_field, instruction_index, immediate);
-
-                let sign: char = if displacement > 0 { '+' } else { '-' };
-                let field: &str = REG_FIELD_ENCODINGS_8_BIT[reg_field as usize];
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                if displacement != 0 {
-                    println!("{} {}, [{} {} {}]", instruction, field, expression, sign, displacement.abs());
-                } else {
-                    println!("{} {}, [{}]", instruction, field, expression);
-                }
-            } else {
-                let immediate: u8 = get_8_bit_register(registers, reg_field);
-                arithmetic_op_with_mem_8_bit(registers, memory, address, instruction_index, immediate);
-
-                let sign: char = if displacement > 0 { '+' } else { '-' };
-                let field: &str = REG_FIELD_ENCODINGS_8_BIT[reg_field as usize];
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                if displacement != 0 {
-                    println!("{} [{} {} {}], {}", instruction, expression, sign, displacement.abs(), field);
-                } else {
-                    println!("{} [{}], {}", instruction, expression, field);
-                }
-            }
-        },
-        MODE_REG => {
-            let (source_index, destination_index): (u8, u8) = if d_bit == 1 {
-                (rm_field, reg_field)
-            } else {
-                (reg_field, rm_field)
-            };
+    registers.ax = ((ah as u16) << 8) + (al & 0x0F) as u16;
+    registers.flags = set_bit(registers.flags, AF_FLAG_BIT, adjust);
+    registers.flags = set_bit(registers.flags, CF_FLAG_BIT, adjust);
+}
 
-            let immediate: u8 = get_8_bit_register(registers, source_index);
-            arithmetic_op_with_reg_8_bit(registers, destination_index, instruction_index, immediate);
+fn ascii_adjust_base(instruction: &Instruction) -> u8 {
+    match instruction.source {
+        Some(Operand::Immediate(base)) => { return base as u8; },
+        _ => { return 10; }
+    }
+}
 
-            let source: &str = REG_FIELD_ENCODINGS_8_BIT[source_index as usize];
-            let destination: &str = REG_FIELD_ENCODINGS_8_BIT[destination_index as usize];
-            let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-            println!("{} {}, {}", instruction, destination, source);
-        },
-        _ => {
-            debug_assert!(false);
-        }
+// Splits AL into two unpacked digits in AH and AL. A base of zero is a divide error.
+pub fn execute_aam(registers: &mut Registers, memory: &mut Memory, hooks: &mut InterruptHooks, instruction: &Instruction) {
+    let base: u8 = ascii_adjust_base(instruction);
+    if base == 0 {
+        dispatch_interrupt(registers, memory, hooks, DIVIDE_ERROR_VECTOR);
+        return;
     }
+
+    let al: u8 = get_low_byte(registers.ax);
+    registers.ax = (((al / base) as u16) << 8) + (al % base) as u16;
+    registers.flags = update_flags_register(registers.flags, registers.ax & 0x00FF, Width::Byte);
 }
 
-pub fn arithmetic_imm_to_reg_mem_16_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    
-    let s_bit: u8 = (byte & 0x02) >> 1;
-    let w_bit: u8 = byte & 0x01;
-    debug_assert!(w_bit == 1);
-
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    
-    let instruction_index: u8 = (byte & 0x38) >> 3;
-    let mod_field: u8 = (byte & 0xC0) >> 6;
-    let rm_field: u8 = byte & 0x07;
-
-    match mod_field {
-        MODE_MEM_NO_DISP => {
-            if rm_field == 6 {
-                let address: u16 = grab_instruction_word(memory, &mut registers.ip);
-                if s_bit == 1 {
-                    let immediate: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
-                    arithmetic_op_with_mem_16_bit(registers, memory, address, instruction_index, immediate as u16);
-                    
-                    let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                    println!("{} word [{}], {}", instruction, address, immediate);
-                } else {
-                    let immediate: u16 = grab_instruction_word(memory, &mut registers.ip);
-                    arithmetic_op_with_mem_16_bit(registers, memory, address, instruction_index, immediate);
-
-                    let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                    println!("{} word [{}], {}", instruction, address, immediate);
-                }
-            } else {
-                let address: u16 = calculate_reg_expression(registers, rm_field);
-                if s_bit == 1 {
-                    let immediate: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
-                    arithmetic_op_with_mem_16_bit(registers, memory, address, instruction_index, immediate as u16);
-                    
-                    let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                    let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                    println!("{} word [{}], {}", instruction, expression, immediate as i8);
-                } else {
-                    let immediate: u16 = grab_instruction_word(memory, &mut registers.ip);
-                    arithmetic_op_with_mem_16_bit(registers, memory, address, instruction_index, immediate);
-
-                    let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                    let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                    println!("{} word [{}], {}", instruction, expression, immediate);
-                }
-            }
-        },
-        MODE_MEM_8_BIT_DISP => {
-            let displacement: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            if s_bit == 1 {
-                let immediate: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
-                arithmetic_op_with_mem_16_bit(registers, memory, address, instruction_index, immediate as u16);
-
-                let sign: char = if displacement > 0 { '+' } else { '-' };
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                if displacement != 0 {
-                    println!("{} word [{} {} {}], {}", instruction, expression, sign, displacement.abs(), immediate);
-                } else {
-                    println!("{} word [{}], {}", instruction, expression, immediate);
-                }
-            } else {
-                let immediate: u16 = grab_instruction_word(memory, &mut registers.ip);
-                arithmetic_op_with_mem_16_bit(registers, memory, address, instruction_index, immediate);
-
-                let sign: char = if displacement > 0 { '+' } else { '-' };
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                if displacement != 0 {
-                    println!("{} word [{} {} {}], {}", instruction, expression, sign, displacement.abs(), immediate);
-                } else {
-                    println!("{} word [{}], {}", instruction, expression, immediate);
-                }
-            }
-        },
-        MODE_MEM_16_BIT_DISP => {
-            let displacement: i16 = grab_instruction_word(memory, &mut registers.ip) as i16;
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            if s_bit == 1 {
-                let immediate: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
-                arithmetic_op_with_mem_16_bit(registers, memory, address, instruction_index, immediate as u16);
-
-                let sign: char = if displacement > 0 { '+' } else { '-' };
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                if displacement != 0 {
-                    println!("{} word [{} {} {}], {}", instruction, expression, sign, displacement.abs(), immediate);
-                } else {
-                    println!("{} word [{}], {}", instruction, expression, immediate);
-                }
-            } else {
-                let immediate: u16 = grab_instruction_word(memory, &mut registers.ip);
-                arithmetic_op_with_mem_16_bit(registers, memory, address, instruction_index, immediate);
-
-                let sign: char = if displacement > 0 { '+' } else { '-' };
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                if displacement != 0 {
-                    println!("{} word [{} {} {}], {}", instruction, expression, sign, displacement.abs(), immediate);
-                } else {
-                    println!("{} word [{}], {}", instruction, expression, immediate);
-                }
-            }
-        },
-        MODE_REG => {
-            if s_bit == 1 {
-                let immediate: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
-                arithmetic_op_with_reg_16_bit(registers, rm_field, instruction_index, immediate as u16);
-
-                let field: &str = REG_FIELD_ENCODINGS_16_BIT[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                println!("{} {}, {}", instruction, field, immediate);
-            } else {
-                let immediate: u16 = grab_instruction_word(memory, &mut registers.ip);
-                arithmetic_op_with_reg_16_bit(registers, rm_field, instruction_index, immediate);
+// Combines the unpacked digits in AH and AL ahead of a division
+pub fn execute_aad(registers: &mut Registers, instruction: &Instruction) {
+    let base: u8 = ascii_adjust_base(instruction);
 
-                let field: &str = REG_FIELD_ENCODINGS_16_BIT[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                println!("{} {}, {}", instruction, field, immediate);
-            }
-        },
-        _ => {
-            debug_assert!(false);
+    let al: u8 = get_low_byte(registers.ax).wrapping_add(get_high_byte(registers.ax).wrapping_mul(base));
+    registers.ax = al as u16;
+    registers.flags = update_flags_register(registers.flags, registers.ax, Width::Byte);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::Machine;
+    use crate::test_helpers::*;
+
+    const ARITHMETIC_FLAG_BITS: u16 = CF_FLAG_BIT | PF_FLAG_BIT | AF_FLAG_BIT | ZF_FLAG_BIT | SF_FLAG_BIT | OF_FLAG_BIT;
+
+    fn check_op(operation: Operation, x: u16, y: u16, width: Width, expected_result: u16, expected_flags: u16) {
+        check_op_with_flags(operation, x, y, width, 0, expected_result, expected_flags);
+    }
+
+    fn check_op_with_flags(operation: Operation, x: u16, y: u16, width: Width, initial_flags: u16, expected_result: u16, expected_flags: u16) {
+        let mut flags_register: u16 = initial_flags;
+        let result: Option<u16> = arithmetic_op(operation, x, y, width, &mut flags_register);
+        if operation != Operation::Cmp {
+            assert_eq!(result, Some(expected_result), "{:?} {:#X}, {:#X}", operation, x, y);
         }
+
+        assert_eq!(flags_register & ARITHMETIC_FLAG_BITS, expected_flags, "{:?} {:#X}, {:#X}", operation, x, y);
     }
-}
 
-pub fn arithmetic_imm_to_reg_mem_8_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    
-    let s_bit: u8 = (byte & 0x02) >> 1;
-    let w_bit: u8 = byte & 0x01;
-    debug_assert!(s_bit == 0);  // Doesn't seem like this is ever set when w_bit == 0 from experimentation
-    debug_assert!(w_bit == 1);
-
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    
-    let instruction_index: u8 = (byte & 0x38) >> 3;
-    let mod_field: u8 = (byte & 0xC0) >> 6;
-    let rm_field: u8 = byte & 0x07;
-
-    match mod_field {
-        MODE_MEM_NO_DISP => {
-            if rm_field == 6 {
-                let address: u16 = grab_instruction_word(memory, &mut registers.ip);
-                let immediate: u8 = grab_instruction_byte(memory, &mut registers.ip);
-                arithmetic_op_with_mem_8_bit(registers, memory, address, instruction_index, immediate);
-
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                println!("{} byte [{}], {}", instruction, address, immediate);
-            } else {
-                let address: u16 = calculate_reg_expression(registers, rm_field);
-                let immediate: u8 = grab_instruction_byte(memory, &mut registers.ip);
-                arithmetic_op_with_mem_8_bit(registers, memory, address, instruction_index, immediate);
+    #[test]
+    fn test_add_flags() {
+        check_op(Operation::Add, 0xFF, 0x01, Width::Byte, 0x00, CF_FLAG_BIT | PF_FLAG_BIT | AF_FLAG_BIT | ZF_FLAG_BIT);
+        check_op(Operation::Add, 0x7F, 0x01, Width::Byte, 0x80, AF_FLAG_BIT | SF_FLAG_BIT | OF_FLAG_BIT);
+        check_op(Operation::Add, 0x80, 0x80, Width::Byte, 0x00, CF_FLAG_BIT | PF_FLAG_BIT | ZF_FLAG_BIT | OF_FLAG_BIT);
+        check_op(Operation::Add, 0x12, 0x21, Width::Byte, 0x33, PF_FLAG_BIT);
+        check_op(Operation::Add, 0xFFFF, 0x0001, Width::Word, 0x0000, CF_FLAG_BIT | PF_FLAG_BIT | AF_FLAG_BIT | ZF_FLAG_BIT);
+        check_op(Operation::Add, 0x7FFF, 0x0001, Width::Word, 0x8000, PF_FLAG_BIT | AF_FLAG_BIT | SF_FLAG_BIT | OF_FLAG_BIT);
+        check_op(Operation::Add, 0x00FF, 0x0001, Width::Word, 0x0100, PF_FLAG_BIT | AF_FLAG_BIT);
+    }
 
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-                println!("{} byte [{}], {}", instruction, expression, immediate);
-            }
-        },
-        MODE_MEM_8_BIT_DISP => {
-            let displacement: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            let immediate: u8 = grab_instruction_byte(memory, &mut registers.ip);
-            arithmetic_op_with_mem_8_bit(registers, memory, address, instruction_index, immediate);
-
-            let sign: char = if displacement > 0 { '+' } else { '-' };
-            let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-            let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-            if displacement != 0 {
-                println!("{} byte [{} {} {}], {}", instruction, expression, sign, displacement.abs(), immediate);
-            } else {
-                println!("{} byte [{}], {}", instruction, expression, immediate);
-            }
-        },
-        MODE_MEM_16_BIT_DISP => {
-            let displacement: i16 = grab_instruction_word(memory, &mut registers.ip) as i16;
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            let immediate: u8 = grab_instruction_byte(memory, &mut registers.ip);
-            arithmetic_op_with_mem_8_bit(registers, memory, address, instruction_index, immediate);
-
-            let sign: char = if displacement > 0 { '+' } else { '-' };
-            let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-            let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-            if displacement != 0 {
-                println!("{} byte [{} {} {}], {}", instruction, expression, sign, displacement.abs(), immediate);
-            } else {
-                println!("{} byte [{}], {}", instruction, expression, immediate);
-            }
-        },
-        MODE_REG => {
-            let immediate: u8 = grab_instruction_byte(memory, &mut registers.ip);
-            arithmetic_op_with_reg_8_bit(registers, rm_field, instruction_index, immediate);
+    #[test]
+    fn test_sub_flags() {
+        check_op(Operation::Sub, 0x00, 0x01, Width::Byte, 0xFF, CF_FLAG_BIT | PF_FLAG_BIT | AF_FLAG_BIT | SF_FLAG_BIT);
+        check_op(Operation::Sub, 0x80, 0x01, Width::Byte, 0x7F, AF_FLAG_BIT | OF_FLAG_BIT);
+        check_op(Operation::Sub, 0x05, 0x05, Width::Byte, 0x00, PF_FLAG_BIT | ZF_FLAG_BIT);
+        check_op(Operation::Sub, 0x0000, 0x0001, Width::Word, 0xFFFF, CF_FLAG_BIT | PF_FLAG_BIT | AF_FLAG_BIT | SF_FLAG_BIT);
+        check_op(Operation::Sub, 0x8000, 0x0001, Width::Word, 0x7FFF, PF_FLAG_BIT | AF_FLAG_BIT | OF_FLAG_BIT);
+        check_op(Operation::Sub, 0x7FFF, 0xFFFF, Width::Word, 0x8000, CF_FLAG_BIT | PF_FLAG_BIT | SF_FLAG_BIT | OF_FLAG_BIT);
+        check_op(Operation::Cmp, 0x0003, 0x0010, Width::Word, 0xFFF3, CF_FLAG_BIT | PF_FLAG_BIT | SF_FLAG_BIT);
+    }
 
-            let field: &str = REG_FIELD_ENCODINGS_8_BIT[rm_field as usize];
-            let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-            println!("{} {}, {}", instruction, field, immediate);
-        },
-        _ => {
-            debug_assert!(false);
+    #[test]
+    fn test_adc_sbb_flags() {
+        // Without a carry in they behave exactly like add and sub
+        check_op_with_flags(Operation::Adc, 0x7F, 0x01, Width::Byte, 0, 0x80, AF_FLAG_BIT | SF_FLAG_BIT | OF_FLAG_BIT);
+        check_op_with_flags(Operation::Sbb, 0x00, 0x01, Width::Byte, 0, 0xFF, CF_FLAG_BIT | PF_FLAG_BIT | AF_FLAG_BIT | SF_FLAG_BIT);
+
+        check_op_with_flags(Operation::Adc, 0xFF, 0x00, Width::Byte, CF_FLAG_BIT, 0x00, CF_FLAG_BIT | PF_FLAG_BIT | AF_FLAG_BIT | ZF_FLAG_BIT);
+        check_op_with_flags(Operation::Adc, 0x7F, 0x00, Width::Byte, CF_FLAG_BIT, 0x80, AF_FLAG_BIT | SF_FLAG_BIT | OF_FLAG_BIT);
+        check_op_with_flags(Operation::Adc, 0xFFFF, 0xFFFF, Width::Word, CF_FLAG_BIT, 0xFFFF, CF_FLAG_BIT | PF_FLAG_BIT | AF_FLAG_BIT | SF_FLAG_BIT);
+        check_op_with_flags(Operation::Adc, 0x1234, 0x1111, Width::Word, CF_FLAG_BIT, 0x2346, 0);
+
+        check_op_with_flags(Operation::Sbb, 0x00, 0x00, Width::Byte, CF_FLAG_BIT, 0xFF, CF_FLAG_BIT | PF_FLAG_BIT | AF_FLAG_BIT | SF_FLAG_BIT);
+        check_op_with_flags(Operation::Sbb, 0x80, 0x00, Width::Byte, CF_FLAG_BIT, 0x7F, AF_FLAG_BIT | OF_FLAG_BIT);
+        check_op_with_flags(Operation::Sbb, 0x0005, 0x0004, Width::Word, CF_FLAG_BIT, 0x0000, PF_FLAG_BIT | ZF_FLAG_BIT);
+        check_op_with_flags(Operation::Sbb, 0x0000, 0xFFFF, Width::Word, CF_FLAG_BIT, 0x0000, CF_FLAG_BIT | PF_FLAG_BIT | AF_FLAG_BIT | ZF_FLAG_BIT);
+    }
+
+    #[test]
+    fn test_multi_word_arithmetic() {
+        let machine_code: &[u8] = &[
+            0xB8, 0xFF, 0xFF,                     // mov ax, 0xFFFF
+            0xBA, 0x01, 0x00,                     // mov dx, 0x0001
+            0x05, 0x01, 0x00,                     // add ax, 1
+            0x83, 0xD2, 0x00,                     // adc dx, 0
+            0x81, 0x06, 0x00, 0x01, 0x01, 0x00,   // add word [256], 1
+            0x11, 0x16, 0x02, 0x01,               // adc [258], dx
+            0x2D, 0x01, 0x00,                     // sub ax, 1
+            0x1B, 0x1E, 0x02, 0x01,               // sbb bx, [258]
+            0x1C, 0x00                            // sbb al, 0
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+        machine.load(256, &[0xFF, 0xFF, 0x05, 0x00]);
+        machine.run_until(machine.program_end());
+
+        // 0x0001FFFF + 1 in dx:ax and 0x0005FFFF + 1 + 0x00020000 in memory
+        assert_eq!(machine.registers().dx, 0x0002);
+        assert_eq!(load_word(machine.memory(), SegmentedAddress::new(0, 258)), 0x0008);
+        assert_eq!(load_word(machine.memory(), SegmentedAddress::new(0, 256)), 0x0000);
+
+        // 0 - 1 borrows from bx, which borrows in turn so al has one more taken off
+        assert_eq!(machine.registers().bx, 0xFFF7);
+        assert_eq!(machine.registers().ax, 0xFFFE);
+        assert_eq!(machine.registers().flags & CF_FLAG_BIT, 0);
+    }
+
+    #[test]
+    fn test_inc_dec() {
+        let machine_code: &[u8] = &[
+            0x40,                                 // inc ax
+            0x4B,                                 // dec bx
+            0xFE, 0xC1,                           // inc cl
+            0xFE, 0x0E, 0x00, 0x01,               // dec byte [256]
+            0xFF, 0x06, 0x02, 0x01                // inc word [258]
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+        machine.load(256, &[0x80, 0x00, 0xFF, 0xFF]);
+        *machine.registers_mut() = Registers { ax: 0x7FFF, bx: 0x0000, cx: 0x00FF, flags: CF_FLAG_BIT, ..Registers::default() };
+
+        machine.step().expect("Failed to step");
+        assert_eq!(machine.registers().ax, 0x8000);
+        assert_eq!(machine.registers().flags & ARITHMETIC_FLAG_BITS, CF_FLAG_BIT | PF_FLAG_BIT | AF_FLAG_BIT | SF_FLAG_BIT | OF_FLAG_BIT);
+
+        // Borrowing doesn't set CF either
+        machine.registers_mut().flags = 0;
+        machine.step().expect("Failed to step");
+        assert_eq!(machine.registers().bx, 0xFFFF);
+        assert_eq!(machine.registers().flags & ARITHMETIC_FLAG_BITS, PF_FLAG_BIT | AF_FLAG_BIT | SF_FLAG_BIT);
+
+        machine.step().expect("Failed to step");
+        assert_eq!(machine.registers().cx, 0x0000);
+        assert_eq!(machine.registers().flags & ARITHMETIC_FLAG_BITS, PF_FLAG_BIT | AF_FLAG_BIT | ZF_FLAG_BIT);
+
+        machine.step().expect("Failed to step");
+        assert_eq!(machine.memory().read_byte(256), 0x7F);
+        assert_eq!(machine.registers().flags & ARITHMETIC_FLAG_BITS, AF_FLAG_BIT | OF_FLAG_BIT);
+
+        machine.step().expect("Failed to step");
+        assert_eq!(load_word(machine.memory(), SegmentedAddress::new(0, 258)), 0x0000);
+        assert_eq!(machine.registers().flags & ARITHMETIC_FLAG_BITS, PF_FLAG_BIT | AF_FLAG_BIT | ZF_FLAG_BIT);
+    }
+
+    #[test]
+    fn test_not_neg() {
+        let machine: Machine = run_machine_code(&[0xF6, 0xD0], Registers { ax: 0x1234, ..Registers::default() });  // not al
+        assert_eq!(machine.registers().ax, 0x12CB);
+
+        let machine: Machine = run_machine_code(&[0xF7, 0xD8], Registers { ax: 0x0001, ..Registers::default() });  // neg ax
+        assert_eq!(machine.registers().ax, 0xFFFF);
+        assert_eq!(machine.registers().flags & ARITHMETIC_FLAG_BITS, CF_FLAG_BIT | PF_FLAG_BIT | AF_FLAG_BIT | SF_FLAG_BIT);
+
+        let machine: Machine = run_machine_code(&[0xF7, 0xD8], Registers { ax: 0x0000, ..Registers::default() });  // neg ax
+        assert_eq!(machine.registers().ax, 0x0000);
+        assert_eq!(machine.registers().flags & ARITHMETIC_FLAG_BITS, PF_FLAG_BIT | ZF_FLAG_BIT);
+
+        let machine: Machine = run_machine_code(&[0xF6, 0xD8], Registers { ax: 0x0080, ..Registers::default() });  // neg al
+        assert_eq!(machine.registers().ax, 0x0080);
+        assert_eq!(machine.registers().flags & ARITHMETIC_FLAG_BITS, CF_FLAG_BIT | SF_FLAG_BIT | OF_FLAG_BIT);
+    }
+
+    #[test]
+    fn test_multiply() {
+        let machine: Machine = run_machine_code(&[0xF6, 0xE3], Registers { ax: 0x0080, bx: 0x0002, ..Registers::default() });  // mul bl
+        assert_eq!(machine.registers().ax, 0x0100);
+        assert_eq!(machine.registers().flags & (CF_FLAG_BIT | OF_FLAG_BIT), CF_FLAG_BIT | OF_FLAG_BIT);
+
+        let machine: Machine = run_machine_code(&[0xF6, 0xEB], Registers { ax: 0x00FF, bx: 0x0002, ..Registers::default() });  // imul bl
+        assert_eq!(machine.registers().ax, 0xFFFE);
+        assert_eq!(machine.registers().flags & (CF_FLAG_BIT | OF_FLAG_BIT), 0);
+
+        let machine: Machine = run_machine_code(&[0xF6, 0xEB], Registers { ax: 0x0040, bx: 0x0002, ..Registers::default() });  // imul bl
+        assert_eq!(machine.registers().ax, 0x0080);
+        assert_eq!(machine.registers().flags & (CF_FLAG_BIT | OF_FLAG_BIT), CF_FLAG_BIT | OF_FLAG_BIT);
+
+        let machine: Machine = run_machine_code(&[0xF7, 0xE3], Registers { ax: 0xFFFF, bx: 0xFFFF, ..Registers::default() });  // mul bx
+        assert_eq!((machine.registers().dx, machine.registers().ax), (0xFFFE, 0x0001));
+        assert_eq!(machine.registers().flags & (CF_FLAG_BIT | OF_FLAG_BIT), CF_FLAG_BIT | OF_FLAG_BIT);
+
+        let machine: Machine = run_machine_code(&[0xF7, 0xEB], Registers { ax: 0xFFFE, bx: 0x0003, ..Registers::default() });  // imul bx
+        assert_eq!((machine.registers().dx, machine.registers().ax), (0xFFFF, 0xFFFA));
+        assert_eq!(machine.registers().flags & (CF_FLAG_BIT | OF_FLAG_BIT), 0);
+    }
+
+    #[test]
+    fn test_divide() {
+        let machine: Machine = run_machine_code(&[0xF6, 0xF3], Registers { ax: 1000, bx: 7, ..Registers::default() });  // div bl
+        assert_eq!(machine.registers().ax, (6 << 8) + 142);
+
+        let machine: Machine = run_machine_code(&[0xF6, 0xFB], Registers { ax: 0xFFF9, bx: 2, ..Registers::default() });  // idiv bl
+        assert_eq!(machine.registers().ax, 0xFFFD);
+
+        let machine: Machine = run_machine_code(&[0xF7, 0xF3], Registers { dx: 0x0001, ax: 0x0000, bx: 2, ..Registers::default() });  // div bx
+        assert_eq!((machine.registers().dx, machine.registers().ax), (0x0000, 0x8000));
+
+        let machine: Machine = run_machine_code(&[0xF7, 0xFB], Registers { dx: 0xFFFE, ax: 0x7960, bx: 1000, ..Registers::default() });  // idiv bx
+        assert_eq!((machine.registers().dx, machine.registers().ax), (0x0000, 0xFF9C));
+    }
+
+    #[test]
+    fn test_divide_error() {
+        // Divide by zero, quotient too big and the most negative signed quotient all raise interrupt 0
+        let cases: &[(&[u8], Registers)] = &[
+            (&[0xF6, 0xF3], Registers { ax: 0x1234, bx: 0, ..Registers::default() }),         // div bl
+            (&[0xF6, 0xF3], Registers { ax: 0x1000, bx: 2, ..Registers::default() }),         // div bl
+            (&[0xF7, 0xF3], Registers { dx: 0x0002, ax: 0, bx: 2, ..Registers::default() }),  // div bx
+            (&[0xF6, 0xFB], Registers { ax: 0xFF80, bx: 1, ..Registers::default() }),         // idiv bl
+            (&[0xF7, 0xFB], Registers { dx: 0x0000, ax: 0x8000, bx: 0xFFFF, ..Registers::default() })  // idiv bx
+        ];
+
+        for (machine_code, registers) in cases {
+            let mut machine = Machine::new();
+            machine.load_program_at(0x0100, machine_code);
+            machine.load(0, &[0x20, 0x00, 0x00, 0x10]);  // vector 0 handler at 0x1000:0x0020
+            let ax: u16 = registers.ax;
+            *machine.registers_mut() = Registers { cs: 0x0100, sp: 0x0100, flags: IF_FLAG_BIT | CF_FLAG_BIT, ..*registers };
+            machine.step().expect("Failed to step");
+
+            assert_eq!(machine.registers().ax, ax);
+            assert_eq!(machine.registers().cs, 0x1000);
+            assert_eq!(machine.registers().ip, 0x0020);
+            assert_eq!(machine.registers().flags, CF_FLAG_BIT);
+            assert_eq!(machine.registers().sp, 0x00FA);
+
+            // Returns to the instruction after the divide
+            let stack_top = SegmentedAddress::new(0, 0x00FA);
+            assert_eq!(load_word(machine.memory(), stack_top), 2);
+            assert_eq!(load_word(machine.memory(), stack_top.offset_by(2)), 0x0100);
+            assert_eq!(load_word(machine.memory(), stack_top.offset_by(4)), IF_FLAG_BIT | CF_FLAG_BIT | FIXED_FLAG_BITS);
         }
     }
-}
 
-pub fn arithmetic_imm_to_acc_16_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
+    #[test]
+    fn test_decimal_adjust() {
+        // 0x19 + 0x28 = 0x41 with AF set from the low digits
+        let machine: Machine = run_machine_code(&[0x27], Registers { ax: 0x0041, flags: AF_FLAG_BIT, ..Registers::default() });  // daa
+        assert_eq!(machine.registers().ax, 0x0047);
+        assert_eq!(machine.registers().flags & (CF_FLAG_BIT | AF_FLAG_BIT), AF_FLAG_BIT);
+
+        // 0x99 + 0x01 = 0x9A carries out of both digits
+        let machine: Machine = run_machine_code(&[0x27], Registers { ax: 0x009A, ..Registers::default() });  // daa
+        assert_eq!(machine.registers().ax, 0x0000);
+        assert_eq!(machine.registers().flags & ARITHMETIC_FLAG_BITS, CF_FLAG_BIT | PF_FLAG_BIT | AF_FLAG_BIT | ZF_FLAG_BIT);
+
+        // 0x10 - 0x01 = 0x0F with AF set from the borrow
+        let machine: Machine = run_machine_code(&[0x2F], Registers { ax: 0x000F, flags: AF_FLAG_BIT, ..Registers::default() });  // das
+        assert_eq!(machine.registers().ax, 0x0009);
+        assert_eq!(machine.registers().flags & (CF_FLAG_BIT | AF_FLAG_BIT), AF_FLAG_BIT);
+
+        // 0x00 - 0x01 = 0xFF borrows out of the top digit too
+        let machine: Machine = run_machine_code(&[0x2F], Registers { ax: 0x00FF, flags: CF_FLAG_BIT | AF_FLAG_BIT, ..Registers::default() });  // das
+        assert_eq!(machine.registers().ax, 0x0099);
+        assert_eq!(machine.registers().flags & (CF_FLAG_BIT | AF_FLAG_BIT | SF_FLAG_BIT), CF_FLAG_BIT | AF_FLAG_BIT | SF_FLAG_BIT);
+    }
+
+    #[test]
+    fn test_ascii_adjust() {
+        // '9' + '5' = 0x6E
+        let machine: Machine = run_machine_code(&[0x37], Registers { ax: 0x006E, ..Registers::default() });  // aaa
+        assert_eq!(machine.registers().ax, 0x0104);
+        assert_eq!(machine.registers().flags & (CF_FLAG_BIT | AF_FLAG_BIT), CF_FLAG_BIT | AF_FLAG_BIT);
 
-    let w_bit: u8 = byte & 0x01;
-    let instruction_index: u8 = (byte & 0x38) >> 3;
-    debug_assert!(w_bit == 1);
+        let machine: Machine = run_machine_code(&[0x37], Registers { ax: 0x0035, ..Registers::default() });  // aaa
+        assert_eq!(machine.registers().ax, 0x0005);
+        assert_eq!(machine.registers().flags & (CF_FLAG_BIT | AF_FLAG_BIT), 0);
 
-    let immediate: u16 = grab_instruction_word(memory, &mut registers.ip);
-    arithmetic_op_with_reg_16_bit(registers, 0, instruction_index, immediate);
+        // '3' - '5' = 0xFE with AF set from the borrow
+        let machine: Machine = run_machine_code(&[0x3F], Registers { ax: 0x02FE, flags: AF_FLAG_BIT, ..Registers::default() });  // aas
+        assert_eq!(machine.registers().ax, 0x0108);
+        assert_eq!(machine.registers().flags & (CF_FLAG_BIT | AF_FLAG_BIT), CF_FLAG_BIT | AF_FLAG_BIT);
 
-    let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-    println!("{} ax, {}", instruction, immediate);
-}
+        let machine: Machine = run_machine_code(&[0xD4, 0x0A], Registers { ax: 0x003F, ..Registers::default() });  // aam
+        assert_eq!(machine.registers().ax, 0x0603);
+        assert_eq!(machine.registers().flags & (PF_FLAG_BIT | ZF_FLAG_BIT | SF_FLAG_BIT), PF_FLAG_BIT);
 
-pub fn arithmetic_imm_to_acc_8_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
+        let machine: Machine = run_machine_code(&[0xD4, 0x10], Registers { ax: 0x00A7, ..Registers::default() });  // aam 16
+        assert_eq!(machine.registers().ax, 0x0A07);
 
-    let w_bit: u8 = byte & 0x01;
-    let instruction_index: u8 = (byte & 0x38) >> 3;
-    debug_assert!(w_bit == 0);
+        let machine: Machine = run_machine_code(&[0xD5, 0x0A], Registers { ax: 0x0603, ..Registers::default() });  // aad
+        assert_eq!(machine.registers().ax, 0x003F);
 
-    let immediate: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    arithmetic_op_with_reg_8_bit(registers, 0, instruction_index, immediate);
+        let machine: Machine = run_machine_code(&[0xD5, 0x07], Registers { ax: 0x0203, ..Registers::default() });  // aad 7
+        assert_eq!(machine.registers().ax, 0x0011);
+    }
 
-    let instruction: &str = ARITHMETIC_INSTRUCTION_ENCODINGS[instruction_index as usize];
-    println!("{} al, {}", instruction, immediate);
+    #[test]
+    fn test_aam_divide_error() {
+        let mut machine = Machine::new();
+        machine.load_program_at(0x0100, &[0xD4, 0x00]);  // aam 0
+        machine.load(0, &[0x00, 0x00, 0x00, 0x20]);  // vector 0 handler at 0x2000:0x0000
+        *machine.registers_mut() = Registers { ax: 0x1234, cs: 0x0100, sp: 0x0100, ..Registers::default() };
+        machine.step().expect("Failed to step");
+
+        assert_eq!(machine.registers().ax, 0x1234);
+        assert_eq!((machine.registers().cs, machine.registers().ip), (0x2000, 0x0000));
+        assert_eq!(load_word(machine.memory(), SegmentedAddress::new(0, 0x00FA)), 2);
+    }
+
+    #[test]
+    fn test_logic_flags() {
+        let mut flags_register: u16 = CF_FLAG_BIT | AF_FLAG_BIT | OF_FLAG_BIT;
+        let result: Option<u16> = arithmetic_op(Operation::Xor, 0x1234, 0x1234, Width::Word, &mut flags_register);
+        assert_eq!(result, Some(0));
+        assert_eq!(flags_register, PF_FLAG_BIT | ZF_FLAG_BIT);
+
+        check_op(Operation::And, 0xF0F0, 0x8F00, Width::Word, 0x8000, PF_FLAG_BIT | SF_FLAG_BIT);
+        check_op(Operation::Or, 0x01, 0x02, Width::Byte, 0x03, PF_FLAG_BIT);
+        check_op(Operation::Xor, 0x80, 0x01, Width::Byte, 0x81, PF_FLAG_BIT | SF_FLAG_BIT);
+
+        let mut flags_register: u16 = CF_FLAG_BIT | OF_FLAG_BIT;
+        let result: Option<u16> = arithmetic_op(Operation::Test, 0x8001, 0x8000, Width::Word, &mut flags_register);
+        assert_eq!(result, None);
+        assert_eq!(flags_register, PF_FLAG_BIT | SF_FLAG_BIT);
+    }
+
+    #[test]
+    fn test_test() {
+        let machine_code: &[u8] = &[
+            0x85, 0xC0,                 // test ax, ax
+            0x74, 0x02,                 // je $+4
+            0xB3, 0x01,                 // mov bl, 1
+            0x84, 0x26, 0x00, 0x01,     // test [256], ah
+            0x75, 0x02,                 // jne $+4
+            0xB7, 0x01,                 // mov bh, 1
+            0xA9, 0x00, 0x80            // test ax, 32768
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+        machine.load(256, &[0x0F]);
+        *machine.registers_mut() = Registers { ax: 0x8100, flags: CF_FLAG_BIT | OF_FLAG_BIT, ..Registers::default() };
+        machine.run_until(machine.program_end());
+
+        // Neither test writes back so ax and memory are unchanged
+        assert_eq!(machine.registers().ax, 0x8100);
+        assert_eq!(machine.memory().read_byte(256), 0x0F);
+        assert_eq!(machine.registers().bx, 0x0001);
+        assert_eq!(machine.registers().flags & ARITHMETIC_FLAG_BITS, PF_FLAG_BIT | SF_FLAG_BIT);
+    }
 }
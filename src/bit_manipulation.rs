@@ -0,0 +1,127 @@
+use crate::registers::*;
+use crate::memory::*;
+use crate::instruction::*;
+use crate::operand::*;
+use crate::arithmetic::*;
+
+// Shifts or rotates one bit, returning the new value, carry and overflow. Overflow is whether
+// the sign changed, which is what the 8086 leaves in OF after the final step of a longer count.
+fn shift_rotate_once(operation: Operation, value: u16, carry: bool, width: Width) -> (u16, bool, bool) {
+    let sign_bit: u16 = width.sign_bit();
+    let top_bit_set: bool = value & sign_bit != 0;
+    let bottom_bit_set: bool = value & 0x0001 != 0;
+
+    let (result, carry_out): (u16, bool) = match operation {
+        Operation::Rol => (((value << 1) | top_bit_set as u16) & width.mask(), top_bit_set),
+        Operation::Ror => ((value >> 1) | if bottom_bit_set { sign_bit } else { 0 }, bottom_bit_set),
+        Operation::Rcl => (((value << 1) | carry as u16) & width.mask(), top_bit_set),
+        Operation::Rcr => ((value >> 1) | if carry { sign_bit } else { 0 }, bottom_bit_set),
+        Operation::Shl => ((value << 1) & width.mask(), top_bit_set),
+        Operation::Shr => (value >> 1, bottom_bit_set),
+        Operation::Sar => ((value >> 1) | (value & sign_bit), bottom_bit_set),
+        _ => {
+            unreachable!("{:?} is not a shift or rotate", operation);
+        }
+    };
+
+    let overflow: bool = (result ^ value) & sign_bit != 0;
+
+    return (result, carry_out, overflow);
+}
+
+// The count in cl isn't masked so the 8086 really does shift up to 255 times. A count of zero
+// leaves the flags alone. Rotates only change CF and OF; AF is undefined for shifts and left alone.
+pub fn execute_shift_rotate(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let destination: Operand = instruction.destination.expect("shifts have a destination");
+    let count_operand: Operand = instruction.source.expect("shifts have a count");
+
+    let count: u16 = read_operand(registers, memory, count_operand, Width::Byte);
+    if count == 0 {
+        return;
+    }
+
+    let mut value: u16 = read_operand(registers, memory, destination, instruction.width);
+    let mut carry: bool = registers.flags & CF_FLAG_BIT != 0;
+    let mut overflow: bool = false;
+    for _ in 0..count {
+        let (result, carry_out, sign_changed): (u16, bool, bool) = shift_rotate_once(instruction.operation, value, carry, instruction.width);
+        value = result;
+        carry = carry_out;
+        overflow = sign_changed;
+    }
+
+    registers.flags = set_bit(registers.flags, CF_FLAG_BIT, carry);
+    registers.flags = set_bit(registers.flags, OF_FLAG_BIT, overflow);
+    if matches!(instruction.operation, Operation::Shl | Operation::Shr | Operation::Sar) {
+        registers.flags = update_flags_register(registers.flags, value, instruction.width);
+    }
+
+    write_operand(registers, memory, destination, instruction.width, value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+    use crate::test_helpers::*;
+
+    const SHIFT_FLAG_BITS: u16 = CF_FLAG_BIT | PF_FLAG_BIT | ZF_FLAG_BIT | SF_FLAG_BIT | OF_FLAG_BIT;
+
+    fn check_shift(machine_code: &[u8], ax: u16, cx: u16, flags: u16, expected_ax: u16, expected_flags: u16) {
+        let machine: Machine = run_machine_code(machine_code, Registers { ax, cx, flags, ..Registers::default() });
+        assert_eq!(machine.registers().ax, expected_ax, "{:02X?} with ax {:#06X}", machine_code, ax);
+        assert_eq!(machine.registers().flags & SHIFT_FLAG_BITS, expected_flags, "{:02X?} with ax {:#06X}", machine_code, ax);
+    }
+
+    #[test]
+    fn test_shift() {
+        check_shift(&[0xD1, 0xE0], 0x4001, 0, 0, 0x8002, SF_FLAG_BIT | OF_FLAG_BIT);                           // shl ax, 1
+        check_shift(&[0xD1, 0xE0], 0xC000, 0, 0, 0x8000, CF_FLAG_BIT | PF_FLAG_BIT | SF_FLAG_BIT);             // shl ax, 1
+        check_shift(&[0xD0, 0xE0], 0x1280, 0, 0, 0x1200, CF_FLAG_BIT | PF_FLAG_BIT | ZF_FLAG_BIT | OF_FLAG_BIT);  // shl al, 1
+        check_shift(&[0xD1, 0xE8], 0x8001, 0, 0, 0x4000, CF_FLAG_BIT | PF_FLAG_BIT | OF_FLAG_BIT);             // shr ax, 1
+        check_shift(&[0xD1, 0xF8], 0x8001, 0, 0, 0xC000, CF_FLAG_BIT | PF_FLAG_BIT | SF_FLAG_BIT);             // sar ax, 1
+        check_shift(&[0xD3, 0xE0], 0x0001, 4, 0, 0x0010, 0);                                                   // shl ax, cl
+        check_shift(&[0xD3, 0xF8], 0x8000, 15, 0, 0xFFFF, PF_FLAG_BIT | SF_FLAG_BIT);                          // sar ax, cl
+
+        // The count isn't masked to five bits like on later processors
+        check_shift(&[0xD3, 0xE0], 0xFFFF, 32, 0, 0x0000, PF_FLAG_BIT | ZF_FLAG_BIT);                          // shl ax, cl
+        check_shift(&[0xD2, 0xE8], 0x12FF, 0xFF, 0, 0x1200, PF_FLAG_BIT | ZF_FLAG_BIT);                        // shr al, cl
+
+        // A zero count changes nothing, not even the flags
+        check_shift(&[0xD3, 0xE0], 0x1234, 0, CF_FLAG_BIT | ZF_FLAG_BIT, 0x1234, CF_FLAG_BIT | ZF_FLAG_BIT);   // shl ax, cl
+    }
+
+    #[test]
+    fn test_rotate() {
+        check_shift(&[0xD1, 0xC0], 0x8001, 0, 0, 0x0003, CF_FLAG_BIT | OF_FLAG_BIT);                           // rol ax, 1
+        check_shift(&[0xD1, 0xC8], 0x8001, 0, 0, 0xC000, CF_FLAG_BIT);                                         // ror ax, 1
+        check_shift(&[0xD0, 0xD0], 0x0080, 0, 0, 0x0000, CF_FLAG_BIT | OF_FLAG_BIT);                           // rcl al, 1
+        check_shift(&[0xD0, 0xD0], 0x0000, 0, CF_FLAG_BIT, 0x0001, 0);                                         // rcl al, 1
+        check_shift(&[0xD1, 0xD8], 0x0001, 0, CF_FLAG_BIT, 0x8000, CF_FLAG_BIT | OF_FLAG_BIT);                 // rcr ax, 1
+        check_shift(&[0xD3, 0xC0], 0x1234, 4, 0, 0x2341, CF_FLAG_BIT | OF_FLAG_BIT);                           // rol ax, cl
+        check_shift(&[0xD2, 0xC8], 0x0012, 4, 0, 0x0021, 0);                                                   // ror al, cl
+
+        // Rotating through carry by the width plus one gets back to the start
+        check_shift(&[0xD3, 0xD0], 0xA5A5, 17, CF_FLAG_BIT, 0xA5A5, CF_FLAG_BIT);                             // rcl ax, cl
+
+        // Rotates leave the other flags alone
+        check_shift(&[0xD1, 0xC0], 0x0001, 0, ZF_FLAG_BIT | PF_FLAG_BIT, 0x0002, ZF_FLAG_BIT | PF_FLAG_BIT);   // rol ax, 1
+    }
+
+    #[test]
+    fn test_shift_memory() {
+        let machine_code: &[u8] = &[
+            0xD1, 0x26, 0x00, 0x01,     // shl word [256], 1
+            0xD2, 0x0E, 0x02, 0x01      // ror byte [258], cl
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+        machine.load(256, &[0x34, 0x12, 0x0F]);
+        machine.registers_mut().cx = 4;
+        machine.run_until(machine.program_end());
+
+        assert_eq!(load_word(machine.memory(), SegmentedAddress::new(0, 256)), 0x2468);
+        assert_eq!(load_byte(machine.memory(), SegmentedAddress::new(0, 258)), 0xF0);
+    }
+}
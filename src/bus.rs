@@ -0,0 +1,81 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Anything that can be mapped into the physical address space. Addresses are relative to the
+// start of the region the device is mapped at. Reads take &self and must not have side effects;
+// use ports::PortDevice for devices whose reads do.
+pub trait Bus {
+    fn read_byte(&self, address: u32) -> u8;
+    fn write_byte(&mut self, address: u32, byte: u8);
+}
+
+// Lets a device be mapped while a clone of the handle is kept to inspect or drive it
+impl<T: Bus> Bus for Rc<RefCell<T>> {
+    fn read_byte(&self, address: u32) -> u8 {
+        return self.borrow().read_byte(address);
+    }
+
+    fn write_byte(&mut self, address: u32, byte: u8) {
+        self.borrow_mut().write_byte(address, byte);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ram {
+    bytes: Vec<u8>
+}
+
+impl Ram {
+    pub fn new(size: usize) -> Self {
+        return Ram { bytes: vec![0; size] };
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        return &self.bytes;
+    }
+}
+
+// Past the end of a device mapped over a larger region, reads float high and writes are dropped
+fn read_or_open_bus(bytes: &[u8], address: u32) -> u8 {
+    return bytes.get(address as usize).copied().unwrap_or(0xFF);
+}
+
+impl Bus for Ram {
+    fn read_byte(&self, address: u32) -> u8 {
+        return read_or_open_bus(&self.bytes, address);
+    }
+
+    fn write_byte(&mut self, address: u32, byte: u8) {
+        if let Some(stored) = self.bytes.get_mut(address as usize) {
+            *stored = byte;
+        }
+    }
+}
+
+// Writes are silently dropped like on real hardware
+#[derive(Debug, Clone)]
+pub struct Rom {
+    bytes: Vec<u8>
+}
+
+impl Rom {
+    pub fn new(bytes: &[u8]) -> Self {
+        return Rom { bytes: bytes.to_vec() };
+    }
+
+    pub fn len(&self) -> usize {
+        return self.bytes.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.bytes.is_empty();
+    }
+}
+
+impl Bus for Rom {
+    fn read_byte(&self, address: u32) -> u8 {
+        return read_or_open_bus(&self.bytes, address);
+    }
+
+    fn write_byte(&mut self, _address: u32, _byte: u8) {}
+}
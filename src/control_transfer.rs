@@ -1,70 +1,276 @@
 use crate::registers::*;
 use crate::memory::*;
+use crate::instruction::*;
+use crate::operand::*;
+use crate::data_transfer::*;
 
-pub fn je(registers: &mut Registers, memory: &mut Memory) {
-    registers.ip += 1;  // Don't need to read first byte as lookup table says what instruction we are
-    let offset: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
-
-    if registers.flags & ZF_FLAG_BIT != 0 {
-        registers.ip = registers.ip.wrapping_add(offset as u16);
+fn jump_offset(instruction: &Instruction) -> u16 {
+    match instruction.destination {
+        Some(Operand::Relative(offset)) => { return offset as u16; },
+        _ => {
+            unreachable!("jumps always have a relative destination");
+        }
     }
+}
 
-    println!("je {}", offset);
+fn flag_set(flags: u16, flag_bit: u16) -> bool {
+    return flags & flag_bit != 0;
 }
 
-pub fn jne(registers: &mut Registers, memory: &mut Memory) {
-    registers.ip += 1;  // Don't need to read first byte as lookup table says what instruction we are
-    let offset: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
+// Odd conditions are the negation of the even condition before them so only eight need testing
+pub fn condition_met(flags: u16, condition: u8) -> bool {
+    let carry: bool = flag_set(flags, CF_FLAG_BIT);
+    let zero: bool = flag_set(flags, ZF_FLAG_BIT);
+    let less: bool = flag_set(flags, SF_FLAG_BIT) != flag_set(flags, OF_FLAG_BIT);  // Signed comparison
 
-    if registers.flags & ZF_FLAG_BIT == 0 {
-        registers.ip = registers.ip.wrapping_add(offset as u16);
-    }
+    let result: bool = match condition >> 1 {
+        0 => flag_set(flags, OF_FLAG_BIT),  // o
+        1 => carry,                         // b
+        2 => zero,                          // e
+        3 => carry || zero,                 // be
+        4 => flag_set(flags, SF_FLAG_BIT),  // s
+        5 => flag_set(flags, PF_FLAG_BIT),  // p
+        6 => less,                          // l
+        7 => less || zero,                  // le
+        _ => {
+            unreachable!("condition is only four bits");
+        }
+    };
 
-    println!("jne {}", offset);
+    return result != (condition & 0x01 != 0);
+}
+
+pub fn jcc(registers: &mut Registers, instruction: &Instruction, condition: u8) {
+    if condition_met(registers.flags, condition) {
+        registers.ip = registers.ip.wrapping_add(jump_offset(instruction));
+    }
 }
 
-pub fn loopnz(registers: &mut Registers, memory: &mut Memory) {
-    registers.ip += 1;  // Don't need to read first byte as lookup table says what instruction we are
-    let offset: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
+// All the loops decrement CX without touching the flags and only jump while CX is non zero
+fn decrement_cx(registers: &mut Registers) -> bool {
+    registers.cx = registers.cx.wrapping_sub(1);
 
-    if registers.flags & ZF_FLAG_BIT == 0 {
-        registers.ip = registers.ip.wrapping_add(offset as u16);
+    return registers.cx != 0;
+}
+
+pub fn loopnz(registers: &mut Registers, instruction: &Instruction) {
+    if decrement_cx(registers) && !flag_set(registers.flags, ZF_FLAG_BIT) {
+        registers.ip = registers.ip.wrapping_add(jump_offset(instruction));
     }
+}
 
-    println!("loopnz {}", offset);
+pub fn loopz(registers: &mut Registers, instruction: &Instruction) {
+    if decrement_cx(registers) && flag_set(registers.flags, ZF_FLAG_BIT) {
+        registers.ip = registers.ip.wrapping_add(jump_offset(instruction));
+    }
 }
 
-pub fn loopz(registers: &mut Registers, memory: &mut Memory) {
-    registers.ip += 1;  // Don't need to read first byte as lookup table says what instruction we are
-    let offset: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
+// loop is a keyword so can't name the instruction that
+pub fn loop_cx(registers: &mut Registers, instruction: &Instruction) {
+    if decrement_cx(registers) {
+        registers.ip = registers.ip.wrapping_add(jump_offset(instruction));
+    }
+}
 
-    if registers.flags & ZF_FLAG_BIT != 0 {
-        registers.ip = registers.ip.wrapping_add(offset as u16);
+pub fn jcxz(registers: &mut Registers, instruction: &Instruction) {
+    if registers.cx == 0 {
+        registers.ip = registers.ip.wrapping_add(jump_offset(instruction));
     }
+}
 
-    println!("loopz {}", offset);
+// Where a near call or jump goes: relative to the next instruction or an absolute offset from a register or memory
+fn near_target(registers: &Registers, memory: &Memory, instruction: &Instruction) -> u16 {
+    match instruction.destination {
+        Some(Operand::Relative(offset)) => { return registers.ip.wrapping_add(offset as u16); },
+        Some(operand) => { return read_operand(registers, memory, operand, Width::Word); },
+        None => {
+            unreachable!("near calls and jumps always have a destination");
+        }
+    }
 }
 
-// loop is a keyword so can't name the isntruction that
-pub fn loop_cx(registers: &mut Registers, memory: &mut Memory) {
-    registers.ip += 1;  // Don't need to read first byte as lookup table says what instruction we are
-    let offset: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
+// A far pointer is stored in memory as the offset followed by the segment
+fn far_target(registers: &Registers, memory: &Memory, instruction: &Instruction) -> SegmentedAddress {
+    match instruction.destination {
+        Some(Operand::FarAddress { segment, offset }) => { return SegmentedAddress::new(segment, offset); },
+        Some(Operand::Memory(memory_operand)) => {
+            let pointer: SegmentedAddress = memory_operand.address(registers);
+            let offset: u16 = load_word(memory, pointer);
+            let segment: u16 = load_word(memory, pointer.offset_by(2));
+            return SegmentedAddress::new(segment, offset);
+        },
+        _ => {
+            unreachable!("far calls and jumps always have a far address or memory destination");
+        }
+    }
+}
 
-    registers.cx -= 1;
-    if registers.cx != 0 {
-        registers.ip = registers.ip.wrapping_add(offset as u16);
+// Bytes of arguments a return drops from the stack
+fn return_argument_bytes(instruction: &Instruction) -> u16 {
+    match instruction.source {
+        Some(Operand::Immediate(bytes)) => { return bytes; },
+        _ => { return 0; }
     }
+}
 
-    println!("loop {}", offset);
+// ip already points at the next instruction which is the return address
+pub fn call(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let target: u16 = near_target(registers, memory, instruction);
+    let return_address: u16 = registers.ip;
+    push_word(registers, memory, return_address);
+    registers.ip = target;
 }
 
-pub fn jcxz(registers: &mut Registers, memory: &mut Memory) {
-    registers.ip += 1;  // Don't need to read first byte as lookup table says what instruction we are
-    let offset: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
+pub fn call_far(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let target: SegmentedAddress = far_target(registers, memory, instruction);
+    let return_segment: u16 = registers.cs;
+    let return_address: u16 = registers.ip;
+    push_word(registers, memory, return_segment);
+    push_word(registers, memory, return_address);
+    registers.cs = target.segment;
+    registers.ip = target.offset;
+}
 
-    if registers.cx == 0 {
-        registers.ip = registers.ip.wrapping_add(offset as u16);
+pub fn jmp(registers: &mut Registers, memory: &Memory, instruction: &Instruction) {
+    registers.ip = near_target(registers, memory, instruction);
+}
+
+pub fn jmp_far(registers: &mut Registers, memory: &Memory, instruction: &Instruction) {
+    let target: SegmentedAddress = far_target(registers, memory, instruction);
+    registers.cs = target.segment;
+    registers.ip = target.offset;
+}
+
+pub fn ret(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    registers.ip = pop_word(registers, memory);
+    registers.sp = registers.sp.wrapping_add(return_argument_bytes(instruction));
+}
+
+pub fn retf(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    registers.ip = pop_word(registers, memory);
+    registers.cs = pop_word(registers, memory);
+    registers.sp = registers.sp.wrapping_add(return_argument_bytes(instruction));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::{Machine, StopReason};
+
+    #[test]
+    fn test_condition_met() {
+        let cases: &[(u16, &[&str])] = &[
+            (0,                         &["jno", "jnb", "jne", "ja", "jns", "jnp", "jnl", "jg"]),
+            (CF_FLAG_BIT,               &["jno", "jb", "jne", "jbe", "jns", "jnp", "jnl", "jg"]),
+            (ZF_FLAG_BIT,               &["jno", "jnb", "je", "jbe", "jns", "jnp", "jnl", "jle"]),
+            (SF_FLAG_BIT,               &["jno", "jnb", "jne", "ja", "js", "jnp", "jl", "jle"]),
+            (OF_FLAG_BIT,               &["jo", "jnb", "jne", "ja", "jns", "jnp", "jl", "jle"]),
+            (SF_FLAG_BIT | OF_FLAG_BIT, &["jo", "jnb", "jne", "ja", "js", "jnp", "jnl", "jg"]),
+            (PF_FLAG_BIT,               &["jno", "jnb", "jne", "ja", "jns", "jp", "jnl", "jg"])
+        ];
+
+        for (flags, taken) in cases {
+            for condition in 0..16 {
+                let mnemonic: &str = CONDITIONAL_JUMP_MNEMONICS[condition as usize];
+                assert_eq!(condition_met(*flags, condition), taken.contains(&mnemonic), "{} with flags 0x{:04X}", mnemonic, flags);
+            }
+        }
+    }
+
+    #[test]
+    fn test_loop() {
+        let machine_code: &[u8] = &[
+            0xB9, 0x04, 0x00,           // mov cx, 4
+            0x83, 0xC0, 0x01,           // add ax, 1
+            0xE2, 0xFB,                 // loop $-3
+            0xE2, 0xFE,                 // loop $+0
+            0xF4                        // hlt
+        ];
+
+        // With CX at zero the second loop wraps CX round and runs another 65535 times
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+        assert_eq!(machine.run(), StopReason::Halted);
+        assert_eq!(machine.registers().ax, 4);
+        assert_eq!(machine.registers().cx, 0);
+        assert_eq!(machine.instruction_count(), 1 + 8 + 0x10000 + 1);
     }
 
-    println!("jcxz {}", offset);
+    #[test]
+    fn test_loopz_loopnz() {
+        let machine_code: &[u8] = &[
+            0xB9, 0x0A, 0x00,           // mov cx, 10
+            0x83, 0xC0, 0x01,           // add ax, 1
+            0x83, 0xF8, 0x03,           // cmp ax, 3
+            0xE0, 0xF8,                 // loopnz $-6
+            0xE1, 0xFE,                 // loopz $+0
+            0xF4                        // hlt
+        ];
+
+        // loopnz leaves when cmp sets ZF, then loopz spins with ZF still set until CX runs out
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+        assert_eq!(machine.run(), StopReason::Halted);
+        assert_eq!(machine.registers().ax, 3);
+        assert_eq!(machine.registers().cx, 0);
+        assert_ne!(machine.registers().flags & ZF_FLAG_BIT, 0);
+        assert_eq!(machine.instruction_count(), 1 + 3 * 3 + 7 + 1);
+    }
+
+    #[test]
+    fn test_near_call_ret() {
+        let machine_code: &[u8] = &[
+            0xBC, 0x00, 0x10,           // mov sp, 0x1000
+            0xE8, 0x05, 0x00,           // call 0x000B
+            0xB9, 0x02, 0x00,           // mov cx, 2
+            0xEB, 0x0C,                 // jmp 0x0017
+            0xB8, 0x01, 0x00,           // mov ax, 1
+            0xBB, 0x14, 0x00,           // mov bx, 0x0014
+            0xFF, 0xE3,                 // jmp bx
+            0xF4,                       // hlt
+            0xC2, 0x00, 0x00,           // ret 0
+            0xF4                        // hlt
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+        machine.run();
+
+        assert_eq!(machine.registers().ax, 1);
+        assert_eq!(machine.registers().cx, 2);
+        assert_eq!(machine.registers().sp, 0x1000);
+        assert_eq!(machine.registers().ip, 0x0018);
+        assert_eq!(machine.memory().read_byte(0x0FFE), 0x06);
+    }
+
+    #[test]
+    fn test_far_call_ret() {
+        let machine_code: &[u8] = &[
+            0xBC, 0x00, 0x10,               // mov sp, 0x1000
+            0x9A, 0x00, 0x00, 0x00, 0x20,   // call 0x2000:0x0000
+            0xFF, 0x1E, 0x00, 0x01,         // call far [256]
+            0xF4                            // hlt
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program_at(0x1000, machine_code);
+        machine.load(0x10100, &[0x05, 0x00, 0x00, 0x20]);   // far pointer to 0x2000:0x0005
+        machine.load(0x20000, &[
+            0xB9, 0x01, 0x00,               // mov cx, 1
+            0xCB,                           // retf
+            0xF4,                           // hlt
+            0xCA, 0x02, 0x00                // retf 2
+        ]);
+        machine.run();
+
+        assert_eq!(machine.registers().cx, 1);
+
+        // Nothing in the second routine pushed an argument so retf 2 drops the word below the return address
+        assert_eq!(machine.registers().cs, 0x1000);
+        assert_eq!(machine.registers().ip, 0x000D);
+        assert_eq!(machine.registers().sp, 0x1002);
+        assert_eq!(machine.memory().read_byte(0x10FFE), 0x00);
+        assert_eq!(machine.memory().read_byte(0x10FFF), 0x10);
+    }
 }
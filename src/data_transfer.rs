@@ -1,656 +1,404 @@
 use crate::registers::*;
 use crate::memory::*;
-use crate::mode::*;
-
-pub fn mov_mem_reg_to_from_reg_16_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    let d_bit: u8 = (byte & 0x2) >> 1;  // 1 <=> reg field gives destination
-    let w_bit: u8 = byte & 0x1;         // 1 <=> wide version of instruction
-    debug_assert!(w_bit == 1);
-    
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-
-    let mod_field: u8 = (byte & 0xC0) >> 6;
-    let reg_field: u8 = (byte & 0x38) >> 3;
-    let rm_field: u8 = byte & 0x07;
-
-    match mod_field {
-        MODE_MEM_NO_DISP => {
-            if rm_field == 6 {
-                let address: u16 = grab_instruction_word(memory, &mut registers.ip);
-                if d_bit == 1 {
-                    let value: u16 = load_word(memory, address);
-                    set_16_bit_register(registers, reg_field, value);
-                } else {
-                    let value: u16 = get_16_bit_register(registers, reg_field);
-                    store_word(memory, address, value);
-                }
-
-                let field: &str = REG_FIELD_ENCODINGS_16_BIT[reg_field as usize];
-                if d_bit == 1 {
-                    println!("mov {}, [{}]", field, address);
-                } else {
-                    println!("mov [{}], {}", address, field);
-                }
-            } else {
-                let address: u16 = calculate_reg_expression(registers, rm_field);
-                if d_bit == 1 {
-                    let value: u16 = load_word(memory, address);
-                    set_16_bit_register(registers, reg_field, value);
-                } else {
-                    let value: u16 = get_16_bit_register(registers, reg_field);
-                    store_word(memory, address, value);
-                }
-
-                let field: &str = REG_FIELD_ENCODINGS_16_BIT[reg_field as usize];
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                if d_bit == 1 {
-                    println!("mov {}, [{}]", field, expression);
-                } else {
-                    println!("mov [{}], {}", expression, field);
-                }
-            }
-        },
-        MODE_MEM_8_BIT_DISP => {
-            let displacement: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            if d_bit == 1 {
-                let value: u16 = load_word(memory, address);
-                set_16_bit_register(registers, reg_field, value);
-            } else {
-                let value: u16 = get_16_bit_register(registers, reg_field);
-                store_word(memory, address, value);
-            }
-
-            let sign: char = if displacement > 0 { '+' } else { '-' };
-            let field: &str = REG_FIELD_ENCODINGS_16_BIT[reg_field as usize];
-            let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-            if d_bit == 1 {
-                if displacement != 0 {
-                    println!("mov {}, [{} {} {}]", field, expression, sign, displacement.abs());
-                } else {
-                    println!("mov {}, [{}]", field, expression);
-                }
-            } else {
-                if displacement != 0 {
-                    println!("mov [{} {} {}], {}", expression, sign, displacement.abs(), field);
-                } else {
-                    println!("mov [{}], {}", expression, field);
-                }
-            }
-        },
-        MODE_MEM_16_BIT_DISP => {
-            let displacement: i16 = grab_instruction_word(memory, &mut registers.ip) as i16;
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            if d_bit == 1 {
-                let value: u16 = load_word(memory, address);
-                set_16_bit_register(registers, reg_field, value);
-            } else {
-                let value: u16 = get_16_bit_register(registers, reg_field);
-                store_word(memory, address,value);
-            }
-
-            let sign: char = if displacement > 0 { '+' } else { '-' };
-            let field: &str = REG_FIELD_ENCODINGS_16_BIT[reg_field as usize];
-            let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-            if d_bit == 1 {
-                if displacement != 0 {
-                    println!("mov {}, [{} {} {}]", field, expression, sign, displacement.abs());
-                } else {
-                    println!("mov {}, [{}]", field, expression);
-                }
-            } else {
-                if displacement != 0 {
-                    println!("mov [{} {} {}], {}", expression, sign, displacement.abs(), field);
-                } else {
-                    println!("mov [{}], {}", expression, field);
-                }
-            }
-        },
-        MODE_REG => {
-            let (source_field, destination_field): (u8, u8) = if d_bit == 1 {
-                (rm_field, reg_field)
-            } else {
-                (reg_field, rm_field)
-            };
-
-            let value: u16 = get_16_bit_register(registers, source_field);
-            set_16_bit_register(registers, destination_field, value);
-
-            let source: &str = REG_FIELD_ENCODINGS_16_BIT[source_field as usize];
-            let destination: &str = REG_FIELD_ENCODINGS_16_BIT[destination_field as usize];
-            println!("mov {}, {}", destination, source);
-        },
-        _ => {
-            debug_assert!(false);
-        }
-    }
+use crate::instruction::*;
+use crate::operand::*;
+
+pub fn execute_mov(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let destination: Operand = instruction.destination.expect("mov has a destination");
+    let source: Operand = instruction.source.expect("mov has a source");
+
+    let value: u16 = read_operand(registers, memory, source, instruction.width);
+    write_operand(registers, memory, destination, instruction.width, value);
 }
 
-pub fn mov_mem_reg_to_from_reg_8_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    let d_bit: u8 = (byte & 0x2) >> 1;  // 1 <=> reg field gives destination
-    let w_bit: u8 = byte & 0x1;         // 1 <=> wide version of instruction
-    debug_assert!(w_bit == 0);
-    
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-
-    let mod_field: u8 = (byte & 0xC0) >> 6;
-    let reg_field: u8 = (byte & 0x38) >> 3;
-    let rm_field: u8 = byte & 0x07;
-
-    match mod_field {
-        MODE_MEM_NO_DISP => {
-            if rm_field == 6 {
-                let address: u16 = grab_instruction_word(memory, &mut registers.ip);
-                if d_bit == 1 {
-                    let value: u8 = load_byte(memory, address);
-                    set_8_bit_register(registers, reg_field, value);
-                } else {
-                    let value: u8 = get_8_bit_register(registers, reg_field);
-                    store_byte(memory, address, value);
-                }
-
-                let field: &str = REG_FIELD_ENCODINGS_8_BIT[reg_field as usize];
-                if d_bit == 1 {
-                    println!("mov {}, [{}]", field, address);
-                } else {
-                    println!("mov [{}], {}", address, field);
-                }
-            } else {
-                let address: u16 = calculate_reg_expression(registers, rm_field);
-                if d_bit == 1 {
-                    let value: u8 = load_byte(memory, address);
-                    set_8_bit_register(registers, reg_field, value);
-                } else {
-                    let value: u8 = get_8_bit_register(registers, reg_field);
-                    store_byte(memory, address, value);
-                }
-
-                let field: &str = REG_FIELD_ENCODINGS_8_BIT[reg_field as usize];
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                if d_bit == 1 {
-                    println!("mov {}, [{}]", field, expression);
-                } else {
-                    println!("mov [{}], {}", expression, field);
-                }
-            }
-        },
-        MODE_MEM_8_BIT_DISP => {
-            let displacement: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            if d_bit == 1 {
-                let value: u8 = load_byte(memory, address);
-                set_8_bit_register(registers, reg_field, value);
-            } else {
-                let value: u8 = get_8_bit_register(registers, reg_field);
-                store_byte(memory, address, value);
-            }
-
-            let sign: char = if displacement > 0 { '+' } else { '-' };
-            let field: &str = REG_FIELD_ENCODINGS_8_BIT[reg_field as usize];
-            let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-            if d_bit == 1 {
-                if displacement != 0 {
-                    println!("mov {}, [{} {} {}]", field, expression, sign, displacement.abs());
-                } else {
-                    println!("mov {}, [{}]", field, expression);
-                }
-            } else {
-                if displacement != 0 {
-                    println!("mov [{} {} {}], {}", expression, sign, displacement.abs(), field);
-                } else {
-                    println!("mov [{}], {}", expression, field);
-                }
-            }
-        },
-        MODE_MEM_16_BIT_DISP => {
-            let displacement: i16 = grab_instruction_word(memory, &mut registers.ip) as i16;
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            if d_bit == 1 {
-                let value: u8 = load_byte(memory, address);
-                set_8_bit_register(registers, reg_field, value);
-            } else {
-                let value: u8 = get_8_bit_register(registers, reg_field);
-                store_byte(memory, address,value);
-            }
-
-            let sign: char = if displacement > 0 { '+' } else { '-' };
-            let field: &str = REG_FIELD_ENCODINGS_8_BIT[reg_field as usize];
-            let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-            if d_bit == 1 {
-                if displacement != 0 {
-                    println!("mov {}, [{} {} {}]", field, expression, sign, displacement.abs());
-                } else {
-                    println!("mov {}, [{}]", field, expression);
-                }
-            } else {
-                if displacement != 0 {
-                    println!("mov [{} {} {}], {}", expression, sign, displacement.abs(), field);
-                } else {
-                    println!("mov [{}], {}", expression, field);
-                }
-            }
-        },
-        MODE_REG => {
-            let (source_field, destination_field): (u8, u8) = if d_bit == 1 {
-                (rm_field, reg_field)
-            } else {
-                (reg_field, rm_field)
-            };
-
-            let value: u8 = get_8_bit_register(registers, source_field);
-            set_8_bit_register(registers, destination_field, value);
-
-            let source: &str = REG_FIELD_ENCODINGS_8_BIT[source_field as usize];
-            let destination: &str = REG_FIELD_ENCODINGS_8_BIT[destination_field as usize];
-            println!("mov {}, {}", destination, source);
-        },
+pub fn execute_xchg(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let destination: Operand = instruction.destination.expect("xchg has a destination");
+    let source: Operand = instruction.source.expect("xchg has a source");
+
+    let x: u16 = read_operand(registers, memory, destination, instruction.width);
+    let y: u16 = read_operand(registers, memory, source, instruction.width);
+    write_operand(registers, memory, destination, instruction.width, y);
+    write_operand(registers, memory, source, instruction.width, x);
+}
+
+fn address_operand(instruction: &Instruction) -> MemoryOperand {
+    match instruction.source {
+        Some(Operand::Memory(memory_operand)) => { return memory_operand; },
         _ => {
-            debug_assert!(false);
+            unreachable!("{:?} always has a memory source", instruction.operation);
         }
     }
 }
 
-pub fn mov_imm_to_reg_mem_16_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    let w_bit: u8 = byte & 0x01;
-    debug_assert!(w_bit == 1);
-
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    debug_assert!(byte & 0x38 == 0);
-    let mod_field: u8 = (byte & 0xC0) >> 6;
-    let rm_field: u8 = byte & 0x07;
-
-    match mod_field {
-        MODE_MEM_NO_DISP => {
-            if rm_field == 6 {
-                let address: u16 = grab_instruction_word(memory, &mut registers.ip);
-                let data: u16 = grab_instruction_word(memory, &mut registers.ip);
-                store_word(memory, address, data);
-
-                println!("mov [{}], word {}", address, data);
-            } else {
-                let address: u16 = calculate_reg_expression(registers, rm_field);
-                let data: u16 = grab_instruction_word(memory, &mut registers.ip);
-                store_word(memory, address, data);
-
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                println!("mov [{}], word {}", expression, data);
-            }
-        },
-        MODE_MEM_8_BIT_DISP => {
-            let displacement: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
-            let data: u16 = grab_instruction_word(memory, &mut registers.ip);
-
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);                
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            store_word(memory, address, data);
-
-            let sign: char = if displacement > 0 { '+' } else { '-' };
-            let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-            println!("mov [{} {} {}], word {}", expression, sign, displacement.abs(), data);
-        },
-        MODE_MEM_16_BIT_DISP => {
-            let displacement: i16 = grab_instruction_word(memory, &mut registers.ip) as i16;
-            let data: u16 = grab_instruction_word(memory, &mut registers.ip);
-
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);                
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            store_word(memory, address, data);                
-
-            let sign: char = if displacement > 0 { '+' } else { '-' };
-            let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-            println!("mov [{} {} {}], word {}", expression, sign, displacement.abs(), data);
-        },
-        MODE_REG => {
-            let data: u16 = grab_instruction_word(memory, &mut registers.ip);
-            set_16_bit_register(registers, rm_field, data);
-
-            let destination: &str = REG_FIELD_ENCODINGS_16_BIT[rm_field as usize];
-            println!("mov {}, {}", destination, data);
-        },
-        _ => {
-            debug_assert!(false);
-        }
+// Only the address is calculated, memory isn't touched
+pub fn execute_lea(registers: &mut Registers, instruction: &Instruction) {
+    let destination: Operand = instruction.destination.expect("lea has a destination");
+
+    let effective_address: u16 = address_operand(instruction).effective_address(registers);
+    write_operand_register(registers, destination, effective_address);
+}
+
+// Loads a far pointer, offset then segment, into a register and DS or ES
+pub fn execute_load_far_pointer(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let destination: Operand = instruction.destination.expect("lds and les have a destination");
+
+    let pointer: SegmentedAddress = address_operand(instruction).address(registers);
+    let offset: u16 = load_word(memory, pointer);
+    let segment: u16 = load_word(memory, pointer.offset_by(2));
+
+    write_operand_register(registers, destination, offset);
+    if instruction.operation == Operation::Lds {
+        registers.ds = segment;
+    } else {
+        registers.es = segment;
     }
 }
 
-pub fn mov_imm_to_reg_mem_8_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    let w_bit: u8 = byte & 0x01;
-    debug_assert!(w_bit == 0);
-
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    debug_assert!(byte & 0x38 == 0);
-    let mod_field: u8 = (byte & 0xC0) >> 6;
-    let rm_field: u8 = byte & 0x07;
-
-    match mod_field {
-        MODE_MEM_NO_DISP => {
-            if rm_field == 6 {
-                let address: u16 = grab_instruction_word(memory, &mut registers.ip);
-                let data: u8 = grab_instruction_byte(memory, &mut registers.ip);
-                store_byte(memory, address, data);
-
-                println!("mov [{}], byte {}", address, data);
-            } else {
-                let address: u16 = calculate_reg_expression(registers, rm_field);
-                let data: u8 = grab_instruction_byte(memory, &mut registers.ip);
-                store_byte(memory, address, data);
-
-                let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-                println!("mov [{}], byte {}", expression, data);
-            }
-        },
-        MODE_MEM_8_BIT_DISP => {
-            let displacement: i8 = grab_instruction_byte(memory, &mut registers.ip) as i8;
-            let data: u8 = grab_instruction_byte(memory, &mut registers.ip);
-
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            store_byte(memory, address, data);
-
-            let sign: char = if displacement > 0 { '+' } else { '-' };
-            let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-            println!("mov [{} {} {}], byte {}", expression, sign, displacement.abs(), data);
-        },
-        MODE_MEM_16_BIT_DISP => {
-            let displacement: i16 = grab_instruction_word(memory, &mut registers.ip) as i16;
-            let data: u8 = grab_instruction_byte(memory, &mut registers.ip);
-
-            let reg_expression: u16 = calculate_reg_expression(registers, rm_field);
-            let address: u16 = reg_expression.wrapping_add(displacement as u16);
-            store_byte(memory, address, data);
-
-            let sign: char = if displacement > 0 { '+' } else { '-' };
-            let expression: &str = REG_EXPRESSION_ENCODINGS[rm_field as usize];
-            println!("mov [{} {} {}], byte {}", expression, sign, displacement.abs(), data);
-        },
-        MODE_REG => {
-            let data: u8 = grab_instruction_byte(memory, &mut registers.ip);
-            set_8_bit_register(registers, rm_field, data);
-
-            let destination: &str = REG_FIELD_ENCODINGS_8_BIT[rm_field as usize];
-            println!("mov {}, {}", destination, data);
-        },
+fn write_operand_register(registers: &mut Registers, operand: Operand, value: u16) {
+    match operand {
+        Operand::Register16(field_index) => { set_16_bit_register(registers, field_index, value); },
         _ => {
-            debug_assert!(false);
+            unreachable!("{:?} is not a 16 bit register", operand);
         }
     }
 }
 
-pub fn mov_imm_to_reg_16_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    let w_bit: u8 = (byte & 0x08) >> 3;
-    let reg_field: u8 = byte & 0x07;
-    debug_assert!(w_bit == 1);
-
-    let immediate: u16 = grab_instruction_word(memory, &mut registers.ip);
-    set_16_bit_register(registers, reg_field, immediate);
+// Looks AL up in a table of bytes at DS:BX
+pub fn execute_xlat(registers: &mut Registers, memory: &Memory, instruction: &Instruction) {
+    let segment_field: u8 = instruction.prefixes.segment_or(DS_SEGMENT_FIELD);
+    let offset: u16 = registers.bx.wrapping_add(get_low_byte(registers.ax) as u16);
+    let address: SegmentedAddress = SegmentedAddress::new(get_segment_register(registers, segment_field), offset);
 
-    let field: &str = REG_FIELD_ENCODINGS_16_BIT[reg_field as usize];
-    println!("mov {}, {}", field, immediate);
+    registers.ax = set_low_byte(registers.ax, load_byte(memory, address));
 }
 
-pub fn mov_imm_to_reg_8_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    let w_bit: u8 = (byte & 0x08) >> 3;
-    let reg_field: u8 = byte & 0x07;
-    debug_assert!(w_bit == 0);
-
-    let immediate: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    set_8_bit_register(registers, reg_field, immediate);
+// The low byte of the flags holds SF, ZF, AF, PF and CF
+pub fn execute_lahf(registers: &mut Registers) {
+    let low_flags: u8 = get_low_byte(registers.flags | FIXED_FLAG_BITS);
+    registers.ax = set_high_byte(registers.ax, low_flags);
+}
 
-    let field: &str = REG_FIELD_ENCODINGS_8_BIT[reg_field as usize];
-    println!("mov {}, {}", field, immediate);
+pub fn execute_sahf(registers: &mut Registers) {
+    let low_flags: u16 = get_high_byte(registers.ax) as u16 & (SF_FLAG_BIT | ZF_FLAG_BIT | AF_FLAG_BIT | PF_FLAG_BIT | CF_FLAG_BIT);
+    registers.flags = (registers.flags & 0xFF00) | low_flags;
 }
 
-pub fn mov_mem_to_acc_16_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    let w_bit: u8 = byte & 0x01;
-    debug_assert!(w_bit == 1);
+// Sign extends AL into AX
+pub fn execute_cbw(registers: &mut Registers) {
+    registers.ax = get_low_byte(registers.ax) as i8 as u16;
+}
 
-    let address: u16 = grab_instruction_word(memory, &mut registers.ip);
-    let data: u16 = load_word(memory, address);
-    registers.ax = data;
+// Sign extends AX into DX:AX
+pub fn execute_cwd(registers: &mut Registers) {
+    registers.dx = if registers.ax & 0x8000 != 0 { 0xFFFF } else { 0x0000 };
+}
 
-    println!("mov ax, [{}]", address);
+fn stack_top(registers: &Registers) -> SegmentedAddress {
+    return SegmentedAddress::new(registers.ss, registers.sp);
 }
 
-pub fn mov_mem_to_acc_8_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    let w_bit: u8 = byte & 0x01;
-    debug_assert!(w_bit == 0);
+// SP wraps within the stack segment
+pub fn push_word(registers: &mut Registers, memory: &mut Memory, value: u16) {
+    registers.sp = registers.sp.wrapping_sub(2);
+    store_word(memory, stack_top(registers), value);
+}
 
-    let address: u16 = grab_instruction_word(memory, &mut registers.ip);
-    let data: u8 = load_byte(memory, address);
-    registers.ax = set_low_byte(registers.ax, data);
+pub fn pop_word(registers: &mut Registers, memory: &mut Memory) -> u16 {
+    let value: u16 = load_word(memory, stack_top(registers));
+    registers.sp = registers.sp.wrapping_add(2);
 
-    println!("mov al, [{}]", address);
+    return value;
 }
 
-pub fn mov_acc_to_mem_16_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    let w_bit: u8 = byte & 0x01;
-    debug_assert!(w_bit == 1);
+pub fn execute_push(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let source: Operand = instruction.source.expect("push has a source");
 
-    let address: u16 = grab_instruction_word(memory, &mut registers.ip);
-    store_word(memory, address, registers.ax);
-
-    println!("mov [{}], ax", address);
+    // SP is decremented before the source is read so push sp pushes the new value like the 8086
+    registers.sp = registers.sp.wrapping_sub(2);
+    let value: u16 = read_operand(registers, memory, source, Width::Word);
+    store_word(memory, stack_top(registers), value);
 }
 
-pub fn mov_acc_to_mem_8_bit(registers: &mut Registers, memory: &mut Memory) {
-    let byte: u8 = grab_instruction_byte(memory, &mut registers.ip);
-    let w_bit: u8 = byte & 0x01;
-    debug_assert!(w_bit == 0);
+pub fn execute_pop(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let destination: Operand = instruction.destination.expect("pop has a destination");
+
+    let value: u16 = pop_word(registers, memory);
+    write_operand(registers, memory, destination, Width::Word, value);
+}
 
-    let address: u16 = grab_instruction_word(memory, &mut registers.ip);
-    let data: u8 = get_low_byte(registers.ax);
-    store_byte(memory, address, data);
+pub fn execute_pushf(registers: &mut Registers, memory: &mut Memory) {
+    let value: u16 = registers.flags | FIXED_FLAG_BITS;
+    push_word(registers, memory, value);
+}
 
-    println!("mov [{}], ax", address);
+pub fn execute_popf(registers: &mut Registers, memory: &mut Memory) {
+    registers.flags = pop_word(registers, memory) & DEFINED_FLAG_BITS;
 }
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use std::fs;
-    use std::io::prelude::*;
-
-    fn produce_machine_code(assembly: &[u8]) -> Vec<u8> {
-        let mut assembly_file = fs::File::create("./temp.asm").expect("Failed to create temp.asm file");
-        assembly_file.write(assembly).expect("Failed to write to temp.asm file");
-        std::process::Command::new("./bin/nasm.exe").arg("./temp.asm").output().expect("Failed to run assembler");
-
-        let machine_code: Vec<u8> = fs::read("./temp").expect("Missing instruction stream file");
-
-        fs::remove_file("./temp").expect("Failed to delete temp file");
-        fs::remove_file("./temp.asm").expect("Failed to delete temp.asm file");
-
-        return machine_code;
-    }
+    use crate::bus::Bus;
+    use crate::registers::*;
+    use crate::memory::*;
+    use crate::Machine;
+    use crate::test_helpers::*;
 
     #[test]
     fn test_mov_imm_to_reg() {
-        let mut machine_code: Vec<u8> = produce_machine_code(
-            b"bits 16\n\
-            mov al, 255"
-        );
-
-        let mut registers = Registers::default();
-        registers.ax = 0xCCCC;
-        let mut memory: Memory = [0; u16::MAX as usize];
-
-        memory[0..machine_code.len()].copy_from_slice(&machine_code);
-
-        decode_mov_imm_to_reg_encoding(&mut registers, &mut memory);
-        assert_eq!(get_low_byte(registers.ax), 0xFF);
-        assert_eq!(get_high_byte(registers.ax), 0xCC);
-
-        machine_code = produce_machine_code(
-            b"bits 16\n\
-            mov ah, 255"
-        );
-
-        registers = Registers::default();
-        registers.ax = 0xCCCC;
-        memory.fill(0);
-
-        memory[0..machine_code.len()].copy_from_slice(&machine_code);
-
-        decode_mov_imm_to_reg_encoding(&mut registers, &mut memory);
-        assert_eq!(get_low_byte(registers.ax), 0xCC);
-        assert_eq!(get_high_byte(registers.ax), 0xFF);
-
-        machine_code = produce_machine_code(
-            b"bits 16\n\
-            mov bl, 255"
-        );
-
-        registers = Registers::default();
-        registers.bx = 0xCCCC;
-        memory.fill(0);
-
-        memory[0..machine_code.len()].copy_from_slice(&machine_code);
-
-        decode_mov_imm_to_reg_encoding(&mut registers, &mut memory);
-        assert_eq!(get_low_byte(registers.bx), 0xFF);
-        assert_eq!(get_high_byte(registers.bx), 0xCC);
-
-        machine_code = produce_machine_code(
-            b"bits 16\n\
-            mov bh, 255"
-        );
-
-        registers = Registers::default();
-        registers.bx = 0xCCCC;
-        memory.fill(0);
-
-        memory[0..machine_code.len()].copy_from_slice(&machine_code);
-
-        decode_mov_imm_to_reg_encoding(&mut registers, &mut memory);
-        assert_eq!(get_low_byte(registers.bx), 0xCC);
-        assert_eq!(get_high_byte(registers.bx), 0xFF);
-
-        machine_code = produce_machine_code(
-            b"bits 16\n\
-            mov cl, 255"
-        );
-
-        registers = Registers::default();
-        registers.cx = 0xCCCC;
-        memory.fill(0);
-
-        memory[0..machine_code.len()].copy_from_slice(&machine_code);
-
-        decode_mov_imm_to_reg_encoding(&mut registers, &mut memory);
-        assert_eq!(get_low_byte(registers.cx), 0xFF);
-        assert_eq!(get_high_byte(registers.cx), 0xCC);
-
-        machine_code = produce_machine_code(
-            b"bits 16\n\
-            mov ch, 255"
-        );
-
-        registers = Registers::default();
-        registers.cx = 0xCCCC;
-        memory.fill(0);
-
-        memory[0..machine_code.len()].copy_from_slice(&machine_code);
-
-        decode_mov_imm_to_reg_encoding(&mut registers, &mut memory);
-        assert_eq!(get_low_byte(registers.cx), 0xCC);
-        assert_eq!(get_high_byte(registers.cx), 0xFF);
-
-        machine_code = produce_machine_code(
-            b"bits 16\n\
-            mov dl, 255"
-        );
-
-        registers = Registers::default();
-        registers.dx = 0xCCCC;
-        memory.fill(0);
-
-        memory[0..machine_code.len()].copy_from_slice(&machine_code);
-
-        decode_mov_imm_to_reg_encoding(&mut registers, &mut memory);
-        assert_eq!(get_low_byte(registers.dx), 0xFF);
-        assert_eq!(get_high_byte(registers.dx), 0xCC);
-
-        machine_code = produce_machine_code(
-            b"bits 16\n\
-            mov dh, 255"
-        );
-
-        registers = Registers::default();
-        registers.dx = 0xCCCC;
-        memory.fill(0);
-
-        memory[0..machine_code.len()].copy_from_slice(&machine_code);
-
-        decode_mov_imm_to_reg_encoding(&mut registers, &mut memory);
-        assert_eq!(get_low_byte(registers.dx), 0xCC);
-        assert_eq!(get_high_byte(registers.dx), 0xFF);
+        let mut registers = Registers { ax: 0xCCCC, ..Registers::default() };
+        let machine: Machine = run_machine_code(&[0xB0, 0xFF], registers);   // mov al, 255
+        assert_eq!(get_low_byte(machine.registers().ax), 0xFF);
+        assert_eq!(get_high_byte(machine.registers().ax), 0xCC);
+
+        registers = Registers { ax: 0xCCCC, ..Registers::default() };
+        let machine: Machine = run_machine_code(&[0xB4, 0xFF], registers);   // mov ah, 255
+        assert_eq!(get_low_byte(machine.registers().ax), 0xCC);
+        assert_eq!(get_high_byte(machine.registers().ax), 0xFF);
+
+        registers = Registers { bx: 0xCCCC, ..Registers::default() };
+        let machine: Machine = run_machine_code(&[0xB3, 0xFF], registers);   // mov bl, 255
+        assert_eq!(get_low_byte(machine.registers().bx), 0xFF);
+        assert_eq!(get_high_byte(machine.registers().bx), 0xCC);
+
+        registers = Registers { bx: 0xCCCC, ..Registers::default() };
+        let machine: Machine = run_machine_code(&[0xB7, 0xFF], registers);   // mov bh, 255
+        assert_eq!(get_low_byte(machine.registers().bx), 0xCC);
+        assert_eq!(get_high_byte(machine.registers().bx), 0xFF);
+
+        registers = Registers { cx: 0xCCCC, ..Registers::default() };
+        let machine: Machine = run_machine_code(&[0xB1, 0xFF], registers);   // mov cl, 255
+        assert_eq!(get_low_byte(machine.registers().cx), 0xFF);
+        assert_eq!(get_high_byte(machine.registers().cx), 0xCC);
+
+        registers = Registers { cx: 0xCCCC, ..Registers::default() };
+        let machine: Machine = run_machine_code(&[0xB5, 0xFF], registers);   // mov ch, 255
+        assert_eq!(get_low_byte(machine.registers().cx), 0xCC);
+        assert_eq!(get_high_byte(machine.registers().cx), 0xFF);
+
+        registers = Registers { dx: 0xCCCC, ..Registers::default() };
+        let machine: Machine = run_machine_code(&[0xB2, 0xFF], registers);   // mov dl, 255
+        assert_eq!(get_low_byte(machine.registers().dx), 0xFF);
+        assert_eq!(get_high_byte(machine.registers().dx), 0xCC);
+
+        registers = Registers { dx: 0xCCCC, ..Registers::default() };
+        let machine: Machine = run_machine_code(&[0xB6, 0xFF], registers);   // mov dh, 255
+        assert_eq!(get_low_byte(machine.registers().dx), 0xCC);
+        assert_eq!(get_high_byte(machine.registers().dx), 0xFF);
     }
-    
+
     #[test]
     fn test_mov_imm_to_reg_wide() {
-        let mut machine_code: Vec<u8> = produce_machine_code(
-            b"bits 16\n\
-            mov ax, 65535"
-        );
+        let machine: Machine = run_machine_code(&[0xB8, 0xFF, 0xFF], Registers::default());  // mov ax, 65535
+        assert_eq!(machine.registers().ax, 0xFFFF);
 
-        let mut registers = Registers::default();
-        let mut memory: Memory = [0; u16::MAX as usize];
+        let machine: Machine = run_machine_code(&[0xBB, 0xFF, 0xFF], Registers::default());  // mov bx, 65535
+        assert_eq!(machine.registers().bx, 0xFFFF);
 
-        memory[0..machine_code.len()].copy_from_slice(&machine_code);
+        let machine: Machine = run_machine_code(&[0xB9, 0xFF, 0xFF], Registers::default());  // mov cx, 65535
+        assert_eq!(machine.registers().cx, 0xFFFF);
 
-        decode_mov_imm_to_reg_encoding(&mut registers, &mut memory);
-        assert_eq!(registers.ax, 0xFFFF);
+        let machine: Machine = run_machine_code(&[0xBA, 0xFF, 0xFF], Registers::default());  // mov dx, 65535
+        assert_eq!(machine.registers().dx, 0xFFFF);
+    }
 
-        machine_code = produce_machine_code(
-            b"bits 16\n\
-            mov bx, 65535"
-        );
-        
-        registers = Registers::default();
-        memory.fill(0);
+    #[test]
+    fn test_mov_segment_register() {
+        let machine_code: &[u8] = &[
+            0xB8, 0x34, 0x12,           // mov ax, 0x1234
+            0x8E, 0xD8,                 // mov ds, ax
+            0x8C, 0x1E, 0x00, 0x01,     // mov [256], ds
+            0x8E, 0x06, 0x00, 0x01      // mov es, [256]
+        ];
+
+        let machine: Machine = run_machine_code(machine_code, Registers::default());
+        assert_eq!(machine.registers().ds, 0x1234);
+        assert_eq!(machine.registers().es, 0x1234);
+        assert_eq!(machine.memory().read_byte(0x12340 + 256), 0x34);
+        assert_eq!(machine.memory().read_byte(0x12340 + 257), 0x12);
+    }
+
+    #[test]
+    fn test_mov_default_segments() {
+        let machine_code: &[u8] = &[
+            0xB8, 0x34, 0x12,           // mov ax, 0x1234
+            0x89, 0x07,                 // mov [bx], ax
+            0x89, 0x46, 0x02,           // mov [bp + 2], ax
+            0x89, 0x06, 0x04, 0x00      // mov [4], ax
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program_at(0x1000, machine_code);
+        machine.registers_mut().ds = 0x2000;
+        machine.registers_mut().ss = 0x3000;
+        machine.run_until(machine.program_end());
+
+        assert_eq!(machine.memory().read_byte(0x20000), 0x34);
+        assert_eq!(machine.memory().read_byte(0x30002), 0x34);
+        assert_eq!(machine.memory().read_byte(0x20004), 0x34);
+        assert_eq!(machine.memory().read_byte(0x10000), 0xB8);
+    }
 
-        memory[0..machine_code.len()].copy_from_slice(&machine_code);
+    #[test]
+    fn test_mov_segment_override() {
+        let machine_code: &[u8] = &[
+            0xB8, 0x34, 0x12,           // mov ax, 0x1234
+            0x26, 0x89, 0x07,           // mov es:[bx], ax
+            0x3E, 0x89, 0x46, 0x02,     // mov ds:[bp + 2], ax
+            0x26, 0x8B, 0x0F            // mov cx, es:[bx]
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program_at(0x1000, machine_code);
+        machine.registers_mut().ds = 0x2000;
+        machine.registers_mut().ss = 0x3000;
+        machine.registers_mut().es = 0x4000;
+        machine.run_until(machine.program_end());
+
+        assert_eq!(machine.memory().read_byte(0x40000), 0x34);
+        assert_eq!(machine.memory().read_byte(0x20002), 0x34);
+        assert_eq!(machine.memory().read_byte(0x30002), 0x00);
+        assert_eq!(machine.registers().cx, 0x1234);
+    }
 
-        decode_mov_imm_to_reg_encoding(&mut registers, &mut memory);
-        assert_eq!(registers.bx, 0xFFFF);
+    #[test]
+    fn test_push_pop() {
+        let machine_code: &[u8] = &[
+            0xB8, 0x34, 0x12,           // mov ax, 0x1234
+            0x50,                       // push ax
+            0x1E,                       // push ds
+            0xFF, 0x36, 0x00, 0x01,     // push word [256]
+            0x5B,                       // pop bx
+            0x07,                       // pop es
+            0x8F, 0x06, 0x02, 0x01      // pop word [258]
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program_at(0x1000, machine_code);
+        machine.registers_mut().ss = 0x2000;
+        machine.registers_mut().sp = 0x0100;
+        machine.memory_mut().load(0x10100, &[0xCD, 0xAB]);
+        machine.run_until(machine.program_end());
+
+        assert_eq!(machine.registers().bx, 0xABCD);
+        assert_eq!(machine.registers().es, 0x1000);
+        assert_eq!(machine.memory().read_byte(0x10102), 0x34);
+        assert_eq!(machine.memory().read_byte(0x10103), 0x12);
+        assert_eq!(machine.registers().sp, 0x0100);
+        assert_eq!(machine.memory().read_byte(0x200FE), 0x34);
+    }
 
-        machine_code = produce_machine_code(
-            b"bits 16\n\
-            mov cx, 65535"
-        );
+    #[test]
+    fn test_push_sp_and_wraparound() {
+        let machine_code: &[u8] = &[
+            0x54,                       // push sp
+            0x5B,                       // pop bx
+            0x54,                       // push sp
+            0x5C                        // pop sp
+        ];
+
+        // The stack wraps around from the bottom to the top of the segment
+        let registers = Registers { ss: 0x2000, sp: 0x0000, ..Registers::default() };
+        let machine: Machine = run_machine_code(machine_code, registers);
+        assert_eq!(machine.registers().bx, 0xFFFE);
+        assert_eq!(machine.registers().sp, 0xFFFE);
+        assert_eq!(machine.memory().read_byte(0x2FFFE), 0xFE);
+        assert_eq!(machine.memory().read_byte(0x2FFFF), 0xFF);
+    }
 
-        registers = Registers::default();
-        memory.fill(0);
-        
-        memory[0..machine_code.len()].copy_from_slice(&machine_code);
+    #[test]
+    fn test_pushf_popf() {
+        let machine_code: &[u8] = &[
+            0x9C,                       // pushf
+            0x58,                       // pop ax
+            0xB9, 0xFF, 0xFF,           // mov cx, 0xFFFF
+            0x51,                       // push cx
+            0x9D                        // popf
+        ];
+
+        let registers = Registers { sp: 0x1000, flags: CF_FLAG_BIT | ZF_FLAG_BIT, ..Registers::default() };
+        let machine: Machine = run_machine_code(machine_code, registers);
+        assert_eq!(machine.registers().ax, 0xF043);
+        assert_eq!(machine.registers().flags, DEFINED_FLAG_BITS);
+    }
 
-        decode_mov_imm_to_reg_encoding(&mut registers, &mut memory);
-        assert_eq!(registers.cx, 0xFFFF);
+    #[test]
+    fn test_xchg() {
+        let machine_code: &[u8] = &[
+            0x93,                       // xchg ax, bx
+            0x86, 0xCD,                 // xchg ch, cl
+            0x87, 0x16, 0x00, 0x01,     // xchg [256], dx
+            0x90                        // nop
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+        machine.load(256, &[0x34, 0x12]);
+        *machine.registers_mut() = Registers { ax: 1, bx: 2, cx: 0xAABB, dx: 0x5678, ..Registers::default() };
+        machine.run_until(machine.program_end());
+
+        assert_eq!((machine.registers().ax, machine.registers().bx), (2, 1));
+        assert_eq!(machine.registers().cx, 0xBBAA);
+        assert_eq!(machine.registers().dx, 0x1234);
+        assert_eq!(load_word(machine.memory(), SegmentedAddress::new(0, 256)), 0x5678);
+    }
 
-        machine_code = produce_machine_code(
-            b"bits 16\n\
-            mov dx, 65535"
-        );
+    #[test]
+    fn test_lea_lds_les() {
+        let machine_code: &[u8] = &[
+            0x8D, 0x42, 0xFE,           // lea ax, [bp + si - 2]
+            0x8D, 0x1E, 0x34, 0x12,     // lea bx, [4660]
+            0xC4, 0x3E, 0x00, 0x01,     // les di, [256]
+            0xC5, 0x36, 0x00, 0x01      // lds si, [256]
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program_at(0x1000, machine_code);
+        machine.load(0x10100, &[0x78, 0x56, 0x34, 0x12]);
+        *machine.registers_mut() = Registers { bp: 0x0100, si: 0x0010, ..*machine.registers() };
+        machine.run_until(machine.program_end());
+
+        assert_eq!(machine.registers().ax, 0x010E);
+        assert_eq!(machine.registers().bx, 0x1234);
+        assert_eq!((machine.registers().es, machine.registers().di), (0x1234, 0x5678));
+        assert_eq!((machine.registers().ds, machine.registers().si), (0x1234, 0x5678));
+    }
 
-        registers = Registers::default();
-        memory.fill(0);
-        
-        memory[0..machine_code.len()].copy_from_slice(&machine_code);
+    #[test]
+    fn test_xlat() {
+        let machine_code: &[u8] = &[
+            0xD7,                       // xlatb
+            0x88, 0xC4,                 // mov ah, al
+            0xB0, 0x01,                 // mov al, 1
+            0x26, 0xD7                  // es xlatb
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+        machine.load(0x10203, &[0x42]);
+        machine.load(0x20201, &[0x24]);
+        *machine.registers_mut() = Registers { ax: 0x0003, bx: 0x0200, ds: 0x1000, es: 0x2000, ..Registers::default() };
+        machine.run_until(machine.program_end());
+
+        assert_eq!(machine.registers().ax, 0x4224);
+    }
 
-        decode_mov_imm_to_reg_encoding(&mut registers, &mut memory);
-        assert_eq!(registers.dx, 0xFFFF);
+    #[test]
+    fn test_flags_and_sign_extension() {
+        let machine_code: &[u8] = &[
+            0x9F,                       // lahf
+            0x88, 0xE3,                 // mov bl, ah
+            0xB4, 0xD5,                 // mov ah, 0xD5
+            0x9E,                       // sahf
+            0xB0, 0x80,                 // mov al, 0x80
+            0x98,                       // cbw
+            0x99                        // cwd
+        ];
+
+        let registers = Registers { flags: OF_FLAG_BIT | ZF_FLAG_BIT | CF_FLAG_BIT, ..Registers::default() };
+        let machine: Machine = run_machine_code(machine_code, registers);
+        assert_eq!(machine.registers().bx, 0x0043);
+        assert_eq!(machine.registers().flags, OF_FLAG_BIT | SF_FLAG_BIT | ZF_FLAG_BIT | AF_FLAG_BIT | PF_FLAG_BIT | CF_FLAG_BIT);
+        assert_eq!(machine.registers().ax, 0xFF80);
+        assert_eq!(machine.registers().dx, 0xFFFF);
     }
 }
@@ -0,0 +1,1358 @@
+use crate::registers::*;
+use crate::memory::*;
+use crate::operand::*;
+use crate::instruction::*;
+use crate::error::*;
+
+const ARITHMETIC_OPERATIONS: &[Operation] = &[
+    Operation::Add, Operation::Or, Operation::Adc, Operation::Sbb, Operation::And, Operation::Sub, Operation::Xor, Operation::Cmp
+];
+
+const MULTIPLY_DIVIDE_OPERATIONS: &[Operation] = &[
+    Operation::Mul, Operation::Imul, Operation::Div, Operation::Idiv
+];
+
+const LOCK_PREFIX: u8 = 0xF0;
+const REPNE_PREFIX: u8 = 0xF2;
+const REP_PREFIX: u8 = 0xF3;
+const ES_PREFIX: u8 = 0x26;
+const CS_PREFIX: u8 = 0x2E;
+const SS_PREFIX: u8 = 0x36;
+const DS_PREFIX: u8 = 0x3E;
+
+// The 8086 accepts any number of prefixes but later processors reject instructions over 15 bytes,
+// so no real code has more than this. It stops a segment full of prefixes decoding forever.
+const MAX_PREFIXES: u8 = 14;
+
+fn width_from_w_bit(w_bit: u8) -> Width {
+    if w_bit == 1 {
+        return Width::Word;
+    } else {
+        return Width::Byte;
+    }
+}
+
+fn grab_immediate(memory: &Memory, ip: &mut SegmentedAddress, width: Width) -> Result<u16, Error> {
+    match width {
+        Width::Byte => { return Ok(grab_instruction_byte(memory, ip)? as u16); },
+        Width::Word => { return grab_instruction_word(memory, ip); }
+    }
+}
+
+fn decode_unimplemented(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let address: SegmentedAddress = *ip;
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+
+    return Err(Error::UnimplementedOpcode { opcode, address });
+}
+
+// Instructions which are just an opcode
+fn decode_single_byte(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let address: SegmentedAddress = *ip;
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let operation: Operation = match opcode {
+        0x27 => Operation::Daa,
+        0x2F => Operation::Das,
+        0x37 => Operation::Aaa,
+        0x3F => Operation::Aas,
+        0x98 => Operation::Cbw,
+        0x99 => Operation::Cwd,
+        0x9C => Operation::Pushf,
+        0x9D => Operation::Popf,
+        0x9E => Operation::Sahf,
+        0x9F => Operation::Lahf,
+        0xCC => Operation::Int3,
+        0xCE => Operation::Into,
+        0xCF => Operation::Iret,
+        0xD7 => Operation::Xlat,
+        0xF4 => Operation::Hlt,
+        0xF5 => Operation::Cmc,
+        0xF8 => Operation::Clc,
+        0xF9 => Operation::Stc,
+        0xFA => Operation::Cli,
+        0xFB => Operation::Sti,
+        0xFC => Operation::Cld,
+        0xFD => Operation::Std,
+        _ => {
+            return Err(Error::UnimplementedOpcode { opcode, address });
+        }
+    };
+
+    return Ok(Instruction::new(opcode, operation, Width::Byte));
+}
+
+fn decode_mov_mem_reg_to_from_reg(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let d_bit: u8 = (opcode & 0x02) >> 1;  // 1 <=> reg field gives destination
+    let width: Width = width_from_w_bit(opcode & 0x01);
+
+    let mod_rm: ModRm = decode_mod_rm(memory, ip, width)?;
+    let rm_operand: Operand = mod_rm.operand;
+    let reg_operand: Operand = Operand::register(mod_rm.reg_field, width);
+    let (destination, source): (Operand, Operand) = if d_bit == 1 {
+        (reg_operand, rm_operand)
+    } else {
+        (rm_operand, reg_operand)
+    };
+
+    return Ok(Instruction {
+        destination: Some(destination),
+        source: Some(source),
+        ..Instruction::new(opcode, Operation::Mov, width)
+    });
+}
+
+fn decode_mov_imm_to_reg_mem(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let address: SegmentedAddress = *ip;
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let width: Width = width_from_w_bit(opcode & 0x01);
+
+    let mod_rm: ModRm = decode_mod_rm(memory, ip, width)?;
+    if mod_rm.reg_field != 0 {
+        return Err(Error::InvalidModRm { opcode, mod_rm: mod_rm.byte, address });
+    }
+    let immediate: u16 = grab_immediate(memory, ip, width)?;
+
+    return Ok(Instruction {
+        destination: Some(mod_rm.operand),
+        source: Some(Operand::Immediate(immediate)),
+        ..Instruction::new(opcode, Operation::Mov, width)
+    });
+}
+
+fn decode_mov_segment_register(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let address: SegmentedAddress = *ip;
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let d_bit: u8 = (opcode & 0x02) >> 1;  // 1 <=> segment register is the destination
+
+    let mod_rm: ModRm = decode_mod_rm(memory, ip, Width::Word)?;
+    if mod_rm.reg_field > 3 {
+        return Err(Error::InvalidModRm { opcode, mod_rm: mod_rm.byte, address });
+    }
+
+    let segment_operand: Operand = Operand::SegmentRegister(mod_rm.reg_field);
+    let (destination, source): (Operand, Operand) = if d_bit == 1 {
+        (segment_operand, mod_rm.operand)
+    } else {
+        (mod_rm.operand, segment_operand)
+    };
+
+    return Ok(Instruction {
+        destination: Some(destination),
+        source: Some(source),
+        ..Instruction::new(opcode, Operation::Mov, Width::Word)
+    });
+}
+
+fn decode_xchg_mem_reg_with_reg(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let width: Width = width_from_w_bit(opcode & 0x01);
+
+    let mod_rm: ModRm = decode_mod_rm(memory, ip, width)?;
+
+    return Ok(Instruction {
+        destination: Some(mod_rm.operand),
+        source: Some(Operand::register(mod_rm.reg_field, width)),
+        ..Instruction::new(opcode, Operation::Xchg, width)
+    });
+}
+
+// Exchanging ax with itself does nothing and is the encoding for nop
+fn decode_xchg_reg_with_acc(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let reg_field: u8 = opcode & 0x07;
+    if reg_field == 0 {
+        return Ok(Instruction::new(opcode, Operation::Nop, Width::Word));
+    }
+
+    return Ok(Instruction {
+        destination: Some(Operand::Register16(0)),
+        source: Some(Operand::Register16(reg_field)),
+        ..Instruction::new(opcode, Operation::Xchg, Width::Word)
+    });
+}
+
+// lea, lds and les load a register from an address so the r/m operand has to be memory
+fn decode_load_address(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let address: SegmentedAddress = *ip;
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let operation: Operation = match opcode {
+        0x8D => Operation::Lea,
+        0xC4 => Operation::Les,
+        0xC5 => Operation::Lds,
+        _ => {
+            return Err(Error::UnimplementedOpcode { opcode, address });
+        }
+    };
+
+    let mod_rm: ModRm = decode_mod_rm(memory, ip, Width::Word)?;
+    if mod_rm.operand.is_register() {
+        return Err(Error::InvalidModRm { opcode, mod_rm: mod_rm.byte, address });
+    }
+
+    return Ok(Instruction {
+        destination: Some(Operand::Register16(mod_rm.reg_field)),
+        source: Some(mod_rm.operand),
+        ..Instruction::new(opcode, operation, Width::Word)
+    });
+}
+
+fn decode_mov_imm_to_reg(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let width: Width = width_from_w_bit((opcode & 0x08) >> 3);
+    let reg_field: u8 = opcode & 0x07;
+
+    let immediate: u16 = grab_immediate(memory, ip, width)?;
+
+    return Ok(Instruction {
+        destination: Some(Operand::register(reg_field, width)),
+        source: Some(Operand::Immediate(immediate)),
+        ..Instruction::new(opcode, Operation::Mov, width)
+    });
+}
+
+fn decode_mov_mem_to_acc(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let width: Width = width_from_w_bit(opcode & 0x01);
+
+    let address: u16 = grab_instruction_word(memory, ip)?;
+
+    return Ok(Instruction {
+        destination: Some(Operand::register(0, width)),
+        source: Some(Operand::Memory(MemoryOperand::direct(address))),
+        ..Instruction::new(opcode, Operation::Mov, width)
+    });
+}
+
+fn decode_mov_acc_to_mem(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let width: Width = width_from_w_bit(opcode & 0x01);
+
+    let address: u16 = grab_instruction_word(memory, ip)?;
+
+    return Ok(Instruction {
+        destination: Some(Operand::Memory(MemoryOperand::direct(address))),
+        source: Some(Operand::register(0, width)),
+        ..Instruction::new(opcode, Operation::Mov, width)
+    });
+}
+
+fn decode_arithmetic_mem_reg_with_reg_to_either(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let operation: Operation = ARITHMETIC_OPERATIONS[((opcode & 0x38) >> 3) as usize];
+    let d_bit: u8 = (opcode & 0x02) >> 1;
+    let width: Width = width_from_w_bit(opcode & 0x01);
+
+    let mod_rm: ModRm = decode_mod_rm(memory, ip, width)?;
+    let rm_operand: Operand = mod_rm.operand;
+    let reg_operand: Operand = Operand::register(mod_rm.reg_field, width);
+    let (destination, source): (Operand, Operand) = if d_bit == 1 {
+        (reg_operand, rm_operand)
+    } else {
+        (rm_operand, reg_operand)
+    };
+
+    return Ok(Instruction {
+        destination: Some(destination),
+        source: Some(source),
+        ..Instruction::new(opcode, operation, width)
+    });
+}
+
+fn decode_arithmetic_imm_to_reg_mem(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let s_bit: u8 = (opcode & 0x02) >> 1;  // 1 <=> 8 bit immediate is sign extended to 16 bits
+    let width: Width = width_from_w_bit(opcode & 0x01);
+
+    let mod_rm: ModRm = decode_mod_rm(memory, ip, width)?;
+    let operation: Operation = ARITHMETIC_OPERATIONS[mod_rm.reg_field as usize];
+    let immediate: u16 = if s_bit == 1 && width == Width::Word {
+        grab_instruction_byte(memory, ip)? as i8 as u16
+    } else {
+        grab_immediate(memory, ip, width)?
+    };
+
+    return Ok(Instruction {
+        destination: Some(mod_rm.operand),
+        source: Some(Operand::Immediate(immediate)),
+        ..Instruction::new(opcode, operation, width)
+    });
+}
+
+fn decode_arithmetic_imm_to_acc(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let operation: Operation = ARITHMETIC_OPERATIONS[((opcode & 0x38) >> 3) as usize];
+    let width: Width = width_from_w_bit(opcode & 0x01);
+
+    let immediate: u16 = grab_immediate(memory, ip, width)?;
+
+    return Ok(Instruction {
+        destination: Some(Operand::register(0, width)),
+        source: Some(Operand::Immediate(immediate)),
+        ..Instruction::new(opcode, operation, width)
+    });
+}
+
+fn decode_inc_dec_reg(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let operation: Operation = if opcode & 0x08 == 0 { Operation::Inc } else { Operation::Dec };
+
+    return Ok(Instruction {
+        destination: Some(Operand::Register16(opcode & 0x07)),
+        ..Instruction::new(opcode, operation, Width::Word)
+    });
+}
+
+// 0xFE only has inc and dec of an 8 bit r/m operand
+fn decode_group_fe(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let address: SegmentedAddress = *ip;
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+
+    let mod_rm: ModRm = decode_mod_rm(memory, ip, Width::Byte)?;
+    let operation: Operation = match mod_rm.reg_field {
+        0 => Operation::Inc,
+        1 => Operation::Dec,
+        _ => {
+            return Err(Error::InvalidModRm { opcode, mod_rm: mod_rm.byte, address });
+        }
+    };
+
+    return Ok(Instruction {
+        destination: Some(mod_rm.operand),
+        ..Instruction::new(opcode, operation, Width::Byte)
+    });
+}
+
+// 0xF6 and 0xF7 pick the operation with the reg field. Only test takes an immediate.
+fn decode_group_f6_f7(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let width: Width = width_from_w_bit(opcode & 0x01);
+
+    let mod_rm: ModRm = decode_mod_rm(memory, ip, width)?;
+    match mod_rm.reg_field {
+        // The 8086 also treats /1 as test
+        0 | 1 => {
+            let immediate: u16 = grab_immediate(memory, ip, width)?;
+            return Ok(Instruction {
+                destination: Some(mod_rm.operand),
+                source: Some(Operand::Immediate(immediate)),
+                ..Instruction::new(opcode, Operation::Test, width)
+            });
+        },
+        2 | 3 => {
+            let operation: Operation = if mod_rm.reg_field == 2 { Operation::Not } else { Operation::Neg };
+            return Ok(Instruction {
+                destination: Some(mod_rm.operand),
+                ..Instruction::new(opcode, operation, width)
+            });
+        },
+        _ => {
+            // The accumulator is implied so the operand is only a source
+            let operation: Operation = MULTIPLY_DIVIDE_OPERATIONS[(mod_rm.reg_field - 4) as usize];
+            return Ok(Instruction {
+                source: Some(mod_rm.operand),
+                ..Instruction::new(opcode, operation, width)
+            });
+        }
+    }
+}
+
+// The count is 1 for 0xD0 and 0xD1 or cl for 0xD2 and 0xD3
+fn decode_shift_rotate(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let address: SegmentedAddress = *ip;
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let v_bit: u8 = (opcode & 0x02) >> 1;  // 1 <=> count is in cl
+    let width: Width = width_from_w_bit(opcode & 0x01);
+
+    let mod_rm: ModRm = decode_mod_rm(memory, ip, width)?;
+    let operation: Operation = match mod_rm.reg_field {
+        0 => Operation::Rol,
+        1 => Operation::Ror,
+        2 => Operation::Rcl,
+        3 => Operation::Rcr,
+        4 => Operation::Shl,
+        5 => Operation::Shr,
+        7 => Operation::Sar,
+        _ => {
+            return Err(Error::InvalidModRm { opcode, mod_rm: mod_rm.byte, address });
+        }
+    };
+    let count: Operand = if v_bit == 1 { Operand::Register8(CL_FIELD) } else { Operand::Immediate(1) };
+
+    return Ok(Instruction {
+        destination: Some(mod_rm.operand),
+        source: Some(count),
+        ..Instruction::new(opcode, operation, width)
+    });
+}
+
+// String instructions have implicit operands: DS:SI as the source, ES:DI as the destination and
+// the accumulator
+fn decode_string(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let address: SegmentedAddress = *ip;
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let width: Width = width_from_w_bit(opcode & 0x01);
+    let operation: Operation = match opcode & 0xFE {
+        0xA4 => Operation::Movs,
+        0xA6 => Operation::Cmps,
+        0xAA => Operation::Stos,
+        0xAC => Operation::Lods,
+        0xAE => Operation::Scas,
+        _ => {
+            return Err(Error::UnimplementedOpcode { opcode, address });
+        }
+    };
+
+    return Ok(Instruction::new(opcode, operation, width));
+}
+
+// The second byte is the number base, only shown when it isn't the usual 10
+fn decode_ascii_adjust_base(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let operation: Operation = if opcode == 0xD4 { Operation::Aam } else { Operation::Aad };
+
+    let base: u8 = grab_instruction_byte(memory, ip)?;
+    let source: Option<Operand> = if base == 10 { None } else { Some(Operand::Immediate(base as u16)) };
+
+    return Ok(Instruction {
+        source,
+        ..Instruction::new(opcode, operation, Width::Byte)
+    });
+}
+
+fn decode_push_pop_reg(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let reg_operand: Operand = Operand::Register16(opcode & 0x07);
+
+    if opcode & 0x08 == 0 {
+        return Ok(Instruction { source: Some(reg_operand), ..Instruction::new(opcode, Operation::Push, Width::Word) });
+    } else {
+        return Ok(Instruction { destination: Some(reg_operand), ..Instruction::new(opcode, Operation::Pop, Width::Word) });
+    }
+}
+
+fn decode_push_pop_segment(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let segment_operand: Operand = Operand::SegmentRegister((opcode & 0x18) >> 3);
+
+    if opcode & 0x01 == 0 {
+        return Ok(Instruction { source: Some(segment_operand), ..Instruction::new(opcode, Operation::Push, Width::Word) });
+    } else {
+        return Ok(Instruction { destination: Some(segment_operand), ..Instruction::new(opcode, Operation::Pop, Width::Word) });
+    }
+}
+
+fn decode_pop_reg_mem(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let address: SegmentedAddress = *ip;
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+
+    let mod_rm: ModRm = decode_mod_rm(memory, ip, Width::Word)?;
+    if mod_rm.reg_field != 0 {
+        return Err(Error::InvalidModRm { opcode, mod_rm: mod_rm.byte, address });
+    }
+
+    return Ok(Instruction {
+        destination: Some(mod_rm.operand),
+        ..Instruction::new(opcode, Operation::Pop, Width::Word)
+    });
+}
+
+// 0xFF picks the operation with the reg field
+fn decode_group_ff(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let address: SegmentedAddress = *ip;
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+
+    let mod_rm: ModRm = decode_mod_rm(memory, ip, Width::Word)?;
+    match mod_rm.reg_field {
+        0 | 1 => {
+            let operation: Operation = if mod_rm.reg_field == 0 { Operation::Inc } else { Operation::Dec };
+            return Ok(Instruction {
+                destination: Some(mod_rm.operand),
+                ..Instruction::new(opcode, operation, Width::Word)
+            });
+        },
+        2 | 4 => {
+            let operation: Operation = if mod_rm.reg_field == 2 { Operation::Call } else { Operation::Jmp };
+            return Ok(Instruction {
+                destination: Some(mod_rm.operand),
+                ..Instruction::new(opcode, operation, Width::Word)
+            });
+        },
+        3 | 5 => {
+            // A far pointer has to come from memory
+            if mod_rm.operand.is_register() {
+                return Err(Error::InvalidModRm { opcode, mod_rm: mod_rm.byte, address });
+            }
+
+            let operation: Operation = if mod_rm.reg_field == 3 { Operation::CallFar } else { Operation::JmpFar };
+            return Ok(Instruction {
+                destination: Some(mod_rm.operand),
+                ..Instruction::new(opcode, operation, Width::Word)
+            });
+        },
+        6 => {
+            return Ok(Instruction {
+                source: Some(mod_rm.operand),
+                ..Instruction::new(opcode, Operation::Push, Width::Word)
+            });
+        },
+        7 => {
+            return Err(Error::InvalidModRm { opcode, mod_rm: mod_rm.byte, address });
+        },
+        _ => {
+            return Err(Error::UnimplementedOpcode { opcode, address });
+        }
+    }
+}
+
+fn decode_test_mem_reg_with_reg(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let width: Width = width_from_w_bit(opcode & 0x01);
+
+    let mod_rm: ModRm = decode_mod_rm(memory, ip, width)?;
+
+    return Ok(Instruction {
+        destination: Some(mod_rm.operand),
+        source: Some(Operand::register(mod_rm.reg_field, width)),
+        ..Instruction::new(opcode, Operation::Test, width)
+    });
+}
+
+fn decode_test_imm_with_acc(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let width: Width = width_from_w_bit(opcode & 0x01);
+
+    let immediate: u16 = grab_immediate(memory, ip, width)?;
+
+    return Ok(Instruction {
+        destination: Some(Operand::register(0, width)),
+        source: Some(Operand::Immediate(immediate)),
+        ..Instruction::new(opcode, Operation::Test, width)
+    });
+}
+
+// Bit 1 picks out over in and bit 3 takes the port from dx rather than an immediate byte
+fn decode_in_out(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let width: Width = width_from_w_bit(opcode & 0x01);
+
+    let port: Operand = if opcode & 0x08 != 0 {
+        Operand::Register16(DX_FIELD)
+    } else {
+        Operand::Immediate(grab_instruction_byte(memory, ip)? as u16)
+    };
+    let accumulator: Operand = Operand::register(0, width);
+
+    if opcode & 0x02 != 0 {
+        return Ok(Instruction {
+            destination: Some(port),
+            source: Some(accumulator),
+            ..Instruction::new(opcode, Operation::Out, width)
+        });
+    }
+
+    return Ok(Instruction {
+        destination: Some(accumulator),
+        source: Some(port),
+        ..Instruction::new(opcode, Operation::In, width)
+    });
+}
+
+fn decode_int(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let vector: u8 = grab_instruction_byte(memory, ip)?;
+
+    return Ok(Instruction {
+        destination: Some(Operand::Immediate(vector as u16)),
+        ..Instruction::new(opcode, Operation::Int, Width::Byte)
+    });
+}
+
+fn decode_short_jump(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let address: SegmentedAddress = *ip;
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let operation: Operation = match opcode {
+        0x70..=0x7F => Operation::Jcc(opcode & 0x0F),
+        0xE0 => Operation::Loopnz,
+        0xE1 => Operation::Loopz,
+        0xE2 => Operation::Loop,
+        0xE3 => Operation::Jcxz,
+        0xEB => Operation::Jmp,
+        _ => {
+            return Err(Error::UnimplementedOpcode { opcode, address });
+        }
+    };
+
+    let offset: i8 = grab_instruction_byte(memory, ip)? as i8;
+
+    return Ok(Instruction {
+        destination: Some(Operand::Relative(offset as i16)),
+        ..Instruction::new(opcode, operation, Width::Byte)
+    });
+}
+
+fn decode_near_call_jump(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let operation: Operation = if opcode == 0xE8 { Operation::Call } else { Operation::Jmp };
+
+    let offset: u16 = grab_instruction_word(memory, ip)?;
+
+    return Ok(Instruction {
+        destination: Some(Operand::Relative(offset as i16)),
+        ..Instruction::new(opcode, operation, Width::Word)
+    });
+}
+
+fn decode_far_call_jump(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let operation: Operation = if opcode == 0x9A { Operation::CallFar } else { Operation::JmpFar };
+
+    let offset: u16 = grab_instruction_word(memory, ip)?;
+    let segment: u16 = grab_instruction_word(memory, ip)?;
+
+    return Ok(Instruction {
+        destination: Some(Operand::FarAddress { segment, offset }),
+        ..Instruction::new(opcode, operation, Width::Word)
+    });
+}
+
+// The optional immediate is the number of bytes of arguments to drop from the stack
+fn decode_return(memory: &Memory, ip: &mut SegmentedAddress) -> Result<Instruction, Error> {
+    let opcode: u8 = grab_instruction_byte(memory, ip)?;
+    let operation: Operation = if opcode & 0x08 == 0 { Operation::Ret } else { Operation::Retf };
+
+    let source: Option<Operand> = if opcode & 0x01 == 0 {
+        Some(Operand::Immediate(grab_instruction_word(memory, ip)?))
+    } else {
+        None
+    };
+
+    return Ok(Instruction {
+        source,
+        ..Instruction::new(opcode, operation, Width::Word)
+    });
+}
+
+type Decoder = fn(&Memory, &mut SegmentedAddress) -> Result<Instruction, Error>;
+const DECODERS: &[Decoder; 256] = &[
+    // 0x00
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_imm_to_acc,
+    decode_arithmetic_imm_to_acc,
+    decode_push_pop_segment,
+    decode_push_pop_segment,
+
+    // 0x08
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_imm_to_acc,
+    decode_arithmetic_imm_to_acc,
+    decode_push_pop_segment,
+    decode_unimplemented,
+
+    // 0x10
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_imm_to_acc,
+    decode_arithmetic_imm_to_acc,
+    decode_push_pop_segment,
+    decode_push_pop_segment,
+
+    // 0x18
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_imm_to_acc,
+    decode_arithmetic_imm_to_acc,
+    decode_push_pop_segment,
+    decode_push_pop_segment,
+
+    // 0x20
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_imm_to_acc,
+    decode_arithmetic_imm_to_acc,
+    decode_unimplemented,
+    decode_single_byte,
+
+    // 0x28
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_imm_to_acc,
+    decode_arithmetic_imm_to_acc,
+    decode_unimplemented,
+    decode_single_byte,
+
+    // 0x30
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_imm_to_acc,
+    decode_arithmetic_imm_to_acc,
+    decode_unimplemented,
+    decode_single_byte,
+
+    // 0x38
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_mem_reg_with_reg_to_either,
+    decode_arithmetic_imm_to_acc,
+    decode_arithmetic_imm_to_acc,
+    decode_unimplemented,
+    decode_single_byte,
+
+    // 0x40
+    decode_inc_dec_reg,
+    decode_inc_dec_reg,
+    decode_inc_dec_reg,
+    decode_inc_dec_reg,
+    decode_inc_dec_reg,
+    decode_inc_dec_reg,
+    decode_inc_dec_reg,
+    decode_inc_dec_reg,
+
+    // 0x48
+    decode_inc_dec_reg,
+    decode_inc_dec_reg,
+    decode_inc_dec_reg,
+    decode_inc_dec_reg,
+    decode_inc_dec_reg,
+    decode_inc_dec_reg,
+    decode_inc_dec_reg,
+    decode_inc_dec_reg,
+
+    // 0x50
+    decode_push_pop_reg,
+    decode_push_pop_reg,
+    decode_push_pop_reg,
+    decode_push_pop_reg,
+    decode_push_pop_reg,
+    decode_push_pop_reg,
+    decode_push_pop_reg,
+    decode_push_pop_reg,
+
+    // 0x58
+    decode_push_pop_reg,
+    decode_push_pop_reg,
+    decode_push_pop_reg,
+    decode_push_pop_reg,
+    decode_push_pop_reg,
+    decode_push_pop_reg,
+    decode_push_pop_reg,
+    decode_push_pop_reg,
+
+    // 0x60
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+
+    // 0x68
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+
+    // 0x70
+    decode_short_jump,
+    decode_short_jump,
+    decode_short_jump,
+    decode_short_jump,
+    decode_short_jump,
+    decode_short_jump,
+    decode_short_jump,
+    decode_short_jump,
+
+    // 0x78
+    decode_short_jump,
+    decode_short_jump,
+    decode_short_jump,
+    decode_short_jump,
+    decode_short_jump,
+    decode_short_jump,
+    decode_short_jump,
+    decode_short_jump,
+
+    // 0x80
+    decode_arithmetic_imm_to_reg_mem,
+    decode_arithmetic_imm_to_reg_mem,
+    decode_arithmetic_imm_to_reg_mem,
+    decode_arithmetic_imm_to_reg_mem,
+    decode_test_mem_reg_with_reg,
+    decode_test_mem_reg_with_reg,
+    decode_xchg_mem_reg_with_reg,
+    decode_xchg_mem_reg_with_reg,
+
+    // 0x88
+    decode_mov_mem_reg_to_from_reg,
+    decode_mov_mem_reg_to_from_reg,
+    decode_mov_mem_reg_to_from_reg,
+    decode_mov_mem_reg_to_from_reg,
+    decode_mov_segment_register,
+    decode_load_address,
+    decode_mov_segment_register,
+    decode_pop_reg_mem,
+
+    // 0x90
+    decode_xchg_reg_with_acc,
+    decode_xchg_reg_with_acc,
+    decode_xchg_reg_with_acc,
+    decode_xchg_reg_with_acc,
+    decode_xchg_reg_with_acc,
+    decode_xchg_reg_with_acc,
+    decode_xchg_reg_with_acc,
+    decode_xchg_reg_with_acc,
+
+    // 0x98
+    decode_single_byte,
+    decode_single_byte,
+    decode_far_call_jump,
+    decode_unimplemented,
+    decode_single_byte,
+    decode_single_byte,
+    decode_single_byte,
+    decode_single_byte,
+
+    // 0xA0
+    decode_mov_mem_to_acc,
+    decode_mov_mem_to_acc,
+    decode_mov_acc_to_mem,
+    decode_mov_acc_to_mem,
+    decode_string,
+    decode_string,
+    decode_string,
+    decode_string,
+
+    // 0xA8
+    decode_test_imm_with_acc,
+    decode_test_imm_with_acc,
+    decode_string,
+    decode_string,
+    decode_string,
+    decode_string,
+    decode_string,
+    decode_string,
+
+    // 0xB0
+    decode_mov_imm_to_reg,
+    decode_mov_imm_to_reg,
+    decode_mov_imm_to_reg,
+    decode_mov_imm_to_reg,
+    decode_mov_imm_to_reg,
+    decode_mov_imm_to_reg,
+    decode_mov_imm_to_reg,
+    decode_mov_imm_to_reg,
+
+    // 0xB8
+    decode_mov_imm_to_reg,
+    decode_mov_imm_to_reg,
+    decode_mov_imm_to_reg,
+    decode_mov_imm_to_reg,
+    decode_mov_imm_to_reg,
+    decode_mov_imm_to_reg,
+    decode_mov_imm_to_reg,
+    decode_mov_imm_to_reg,
+
+    // 0xC0
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_return,
+    decode_return,
+    decode_load_address,
+    decode_load_address,
+    decode_mov_imm_to_reg_mem,
+    decode_mov_imm_to_reg_mem,
+
+    // 0xC8
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_return,
+    decode_return,
+    decode_single_byte,
+    decode_int,
+    decode_single_byte,
+    decode_single_byte,
+
+    // 0xD0
+    decode_shift_rotate,
+    decode_shift_rotate,
+    decode_shift_rotate,
+    decode_shift_rotate,
+    decode_ascii_adjust_base,
+    decode_ascii_adjust_base,
+    decode_unimplemented,
+    decode_single_byte,
+
+    // 0xD8
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+
+    // 0xE0
+    decode_short_jump,
+    decode_short_jump,
+    decode_short_jump,
+    decode_short_jump,
+    decode_in_out,
+    decode_in_out,
+    decode_in_out,
+    decode_in_out,
+
+    // 0xE8
+    decode_near_call_jump,
+    decode_near_call_jump,
+    decode_far_call_jump,
+    decode_short_jump,
+    decode_in_out,
+    decode_in_out,
+    decode_in_out,
+    decode_in_out,
+
+    // 0xF0
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_unimplemented,
+    decode_single_byte,
+    decode_single_byte,
+    decode_group_f6_f7,
+    decode_group_f6_f7,
+
+    // 0xF8
+    decode_single_byte,
+    decode_single_byte,
+    decode_single_byte,
+    decode_single_byte,
+    decode_single_byte,
+    decode_single_byte,
+    decode_group_fe,
+    decode_group_ff
+];
+
+// Decodes the instruction starting at cs:address without executing it
+pub fn decode(memory: &Memory, cs: u16, address: u16) -> Result<Instruction, Error> {
+    let mut ip = SegmentedAddress::new(cs, address);
+
+    // Prefixes can come in any order; with repeated segment prefixes the last one wins
+    let mut prefixes = Prefixes::default();
+    let mut prefix_count: u8 = 0;
+    loop {
+        match peek_instruction_byte(memory, ip)? {
+            LOCK_PREFIX => { prefixes.lock = true; },
+            REPNE_PREFIX => { prefixes.repeat = Some(Repeat::Repne); },
+            REP_PREFIX => { prefixes.repeat = Some(Repeat::Rep); },
+            ES_PREFIX => { prefixes.segment = Some(ES_SEGMENT_FIELD); },
+            CS_PREFIX => { prefixes.segment = Some(CS_SEGMENT_FIELD); },
+            SS_PREFIX => { prefixes.segment = Some(SS_SEGMENT_FIELD); },
+            DS_PREFIX => { prefixes.segment = Some(DS_SEGMENT_FIELD); },
+            _ => { break; }
+        }
+        ip = ip.offset_by(1);
+
+        prefix_count += 1;
+        if prefix_count > MAX_PREFIXES {
+            return Err(Error::TooManyPrefixes { address: SegmentedAddress::new(cs, address) });
+        }
+    }
+
+    let opcode: u8 = peek_instruction_byte(memory, ip)?;
+    let decoder: Decoder = DECODERS[opcode as usize];
+    let mut instruction: Instruction = decoder(memory, &mut ip)?;
+
+    if let Some(segment_field) = prefixes.segment {
+        instruction.destination = instruction.destination.map(|operand| operand.with_segment_override(segment_field));
+        instruction.source = instruction.source.map(|operand| operand.with_segment_override(segment_field));
+    }
+
+    instruction.address = address;
+    instruction.length = ip.offset.wrapping_sub(address) as u8;
+    instruction.prefixes = prefixes;
+
+    return Ok(instruction);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_machine_code(machine_code: &[u8]) -> Instruction {
+        let mut memory = Memory::new();
+        memory.load(0, machine_code);
+
+        return decode(&memory, 0, 0).expect("Failed to decode instruction");
+    }
+
+    #[test]
+    fn test_decode_mov() {
+        let instruction: Instruction = decode_machine_code(&[0x89, 0xDE]);
+        assert_eq!(instruction.operation, Operation::Mov);
+        assert_eq!(instruction.width, Width::Word);
+        assert_eq!(instruction.destination, Some(Operand::Register16(6)));
+        assert_eq!(instruction.source, Some(Operand::Register16(3)));
+        assert_eq!(instruction.length, 2);
+        assert_eq!(instruction.to_string(), "mov si, bx");
+
+        let instruction: Instruction = decode_machine_code(&[0x8A, 0x00]);
+        assert_eq!(instruction.width, Width::Byte);
+        assert_eq!(instruction.to_string(), "mov al, [bx + si]");
+
+        let instruction: Instruction = decode_machine_code(&[0x8B, 0x56, 0xE0]);
+        assert_eq!(instruction.length, 3);
+        assert_eq!(instruction.to_string(), "mov dx, [bp - 32]");
+
+        let instruction: Instruction = decode_machine_code(&[0x8B, 0x46, 0x00]);
+        assert_eq!(instruction.to_string(), "mov ax, [bp]");
+
+        let instruction: Instruction = decode_machine_code(&[0xA3, 0xE8, 0x03]);
+        assert_eq!(instruction.to_string(), "mov [1000], ax");
+
+        let instruction: Instruction = decode_machine_code(&[0xC7, 0x87, 0x10, 0x27, 0x2C, 0x01]);
+        assert_eq!(instruction.length, 6);
+        assert_eq!(instruction.to_string(), "mov word [bx + 10000], 300");
+
+        let instruction: Instruction = decode_machine_code(&[0x8E, 0xC0]);
+        assert_eq!(instruction.destination, Some(Operand::SegmentRegister(0)));
+        assert_eq!(instruction.to_string(), "mov es, ax");
+
+        let instruction: Instruction = decode_machine_code(&[0x8C, 0x5F, 0x02]);
+        assert_eq!(instruction.to_string(), "mov [bx + 2], ds");
+    }
+
+    #[test]
+    fn test_decode_data_movement() {
+        let instruction: Instruction = decode_machine_code(&[0x90]);
+        assert_eq!(instruction.operation, Operation::Nop);
+        assert_eq!(instruction.to_string(), "nop");
+
+        assert_eq!(decode_machine_code(&[0x93]).to_string(), "xchg ax, bx");
+        assert_eq!(decode_machine_code(&[0x86, 0xE0]).to_string(), "xchg al, ah");
+        assert_eq!(decode_machine_code(&[0x87, 0x4E, 0x02]).to_string(), "xchg [bp + 2], cx");
+
+        let instruction: Instruction = decode_machine_code(&[0x8D, 0x42, 0x04]);
+        assert_eq!(instruction.operation, Operation::Lea);
+        assert_eq!(instruction.length, 3);
+        assert_eq!(instruction.to_string(), "lea ax, [bp + si + 4]");
+
+        assert_eq!(decode_machine_code(&[0xC4, 0x3F]).to_string(), "les di, [bx]");
+        assert_eq!(decode_machine_code(&[0xC5, 0x36, 0xE8, 0x03]).to_string(), "lds si, [1000]");
+        assert_eq!(decode_machine_code(&[0xD7]).to_string(), "xlatb");
+        assert_eq!(decode_machine_code(&[0x26, 0xD7]).to_string(), "es xlatb");
+        assert_eq!(decode_machine_code(&[0x9E]).to_string(), "sahf");
+        assert_eq!(decode_machine_code(&[0x9F]).to_string(), "lahf");
+        assert_eq!(decode_machine_code(&[0x98]).to_string(), "cbw");
+        assert_eq!(decode_machine_code(&[0x99]).to_string(), "cwd");
+    }
+
+    #[test]
+    fn test_decode_arithmetic() {
+        let instruction: Instruction = decode_machine_code(&[0x83, 0x47, 0x04, 0x1D]);
+        assert_eq!(instruction.operation, Operation::Add);
+        assert_eq!(instruction.length, 4);
+        assert_eq!(instruction.to_string(), "add word [bx + 4], 29");
+
+        let instruction: Instruction = decode_machine_code(&[0x80, 0x3E, 0xE8, 0x03, 0x05]);
+        assert_eq!(instruction.operation, Operation::Cmp);
+        assert_eq!(instruction.to_string(), "cmp byte [1000], 5");
+
+        let instruction: Instruction = decode_machine_code(&[0x83, 0xE9, 0xFF]);
+        assert_eq!(instruction.source, Some(Operand::Immediate(0xFFFF)));
+
+        let instruction: Instruction = decode_machine_code(&[0x2C, 0x09]);
+        assert_eq!(instruction.to_string(), "sub al, 9");
+
+        let instruction: Instruction = decode_machine_code(&[0xF0, 0x01, 0x07]);
+        assert!(instruction.prefixes.lock);
+        assert_eq!(instruction.length, 3);
+        assert_eq!(instruction.to_string(), "lock add [bx], ax");
+    }
+
+    #[test]
+    fn test_decode_segment_override() {
+        let instruction: Instruction = decode_machine_code(&[0x26, 0x8B, 0x00]);
+        assert_eq!(instruction.prefixes.segment, Some(ES_SEGMENT_FIELD));
+        assert_eq!(instruction.length, 3);
+        assert_eq!(instruction.to_string(), "mov ax, es:[bx + si]");
+
+        let instruction: Instruction = decode_machine_code(&[0x2E, 0xC7, 0x06, 0xE8, 0x03, 0x01, 0x00]);
+        assert_eq!(instruction.to_string(), "mov word cs:[1000], 1");
+
+        // Repeated segment prefixes are legal and the last one applies
+        let instruction: Instruction = decode_machine_code(&[0x36, 0x3E, 0xF0, 0x01, 0x46, 0x02]);
+        assert_eq!(instruction.length, 6);
+        assert_eq!(instruction.to_string(), "lock add ds:[bp + 2], ax");
+
+        // Nothing to attach the prefix to so it is shown on its own
+        let instruction: Instruction = decode_machine_code(&[0x26, 0xF4]);
+        assert_eq!(instruction.to_string(), "es hlt");
+    }
+
+    #[test]
+    fn test_decode_inc_dec() {
+        let instruction: Instruction = decode_machine_code(&[0x41]);
+        assert_eq!(instruction.operation, Operation::Inc);
+        assert_eq!(instruction.to_string(), "inc cx");
+
+        assert_eq!(decode_machine_code(&[0x4F]).to_string(), "dec di");
+        assert_eq!(decode_machine_code(&[0xFE, 0xC4]).to_string(), "inc ah");
+        assert_eq!(decode_machine_code(&[0xFE, 0x0F]).to_string(), "dec byte [bx]");
+        assert_eq!(decode_machine_code(&[0xFF, 0x06, 0xE8, 0x03]).to_string(), "inc word [1000]");
+        assert_eq!(decode_machine_code(&[0xFF, 0x4E, 0xFE]).to_string(), "dec word [bp - 2]");
+    }
+
+    #[test]
+    fn test_decode_group_f6_f7() {
+        let instruction: Instruction = decode_machine_code(&[0xF6, 0xC3, 0x01]);
+        assert_eq!(instruction.operation, Operation::Test);
+        assert_eq!(instruction.length, 3);
+        assert_eq!(instruction.to_string(), "test bl, 1");
+
+        let instruction: Instruction = decode_machine_code(&[0xF7, 0x06, 0xE8, 0x03, 0x00, 0x80]);
+        assert_eq!(instruction.length, 6);
+        assert_eq!(instruction.to_string(), "test word [1000], 32768");
+
+        assert_eq!(decode_machine_code(&[0x85, 0xC0]).to_string(), "test ax, ax");
+        assert_eq!(decode_machine_code(&[0x84, 0x5F, 0x02]).to_string(), "test [bx + 2], bl");
+        assert_eq!(decode_machine_code(&[0xA8, 0x80]).to_string(), "test al, 128");
+        assert_eq!(decode_machine_code(&[0xA9, 0x00, 0x01]).to_string(), "test ax, 256");
+
+        assert_eq!(decode_machine_code(&[0xF6, 0x17]).to_string(), "not byte [bx]");
+        assert_eq!(decode_machine_code(&[0xF7, 0xD8]).to_string(), "neg ax");
+        assert_eq!(decode_machine_code(&[0xF6, 0xE1]).to_string(), "mul cl");
+        assert_eq!(decode_machine_code(&[0xF7, 0x6E, 0x04]).to_string(), "imul word [bp + 4]");
+        assert_eq!(decode_machine_code(&[0xF7, 0xF3]).to_string(), "div bx");
+        assert_eq!(decode_machine_code(&[0xF6, 0x3C]).to_string(), "idiv byte [si]");
+    }
+
+    #[test]
+    fn test_decode_shift_rotate() {
+        let instruction: Instruction = decode_machine_code(&[0xD1, 0xE0]);
+        assert_eq!(instruction.operation, Operation::Shl);
+        assert_eq!(instruction.source, Some(Operand::Immediate(1)));
+        assert_eq!(instruction.to_string(), "shl ax, 1");
+
+        assert_eq!(decode_machine_code(&[0xD0, 0xC3]).to_string(), "rol bl, 1");
+        assert_eq!(decode_machine_code(&[0xD3, 0xCA]).to_string(), "ror dx, cl");
+        assert_eq!(decode_machine_code(&[0xD2, 0x17]).to_string(), "rcl byte [bx], cl");
+        assert_eq!(decode_machine_code(&[0xD1, 0x1E, 0xE8, 0x03]).to_string(), "rcr word [1000], 1");
+        assert_eq!(decode_machine_code(&[0xD3, 0x6E, 0x02]).to_string(), "shr word [bp + 2], cl");
+        assert_eq!(decode_machine_code(&[0xD0, 0xF8]).to_string(), "sar al, 1");
+    }
+
+    #[test]
+    fn test_decode_string() {
+        let instruction: Instruction = decode_machine_code(&[0xA4]);
+        assert_eq!(instruction.operation, Operation::Movs);
+        assert_eq!(instruction.width, Width::Byte);
+        assert_eq!(instruction.to_string(), "movsb");
+
+        let instruction: Instruction = decode_machine_code(&[0xF3, 0xA5]);
+        assert_eq!(instruction.prefixes.repeat, Some(Repeat::Rep));
+        assert_eq!(instruction.length, 2);
+        assert_eq!(instruction.to_string(), "rep movsw");
+
+        assert_eq!(decode_machine_code(&[0xF3, 0xA6]).to_string(), "repe cmpsb");
+        assert_eq!(decode_machine_code(&[0xF2, 0xAF]).to_string(), "repne scasw");
+        assert_eq!(decode_machine_code(&[0xAC]).to_string(), "lodsb");
+        assert_eq!(decode_machine_code(&[0xF3, 0xAB]).to_string(), "rep stosw");
+        assert_eq!(decode_machine_code(&[0xF3, 0x26, 0xA4]).to_string(), "rep es movsb");
+    }
+
+    #[test]
+    fn test_decode_decimal_adjust() {
+        assert_eq!(decode_machine_code(&[0x27]).to_string(), "daa");
+        assert_eq!(decode_machine_code(&[0x2F]).to_string(), "das");
+        assert_eq!(decode_machine_code(&[0x37]).to_string(), "aaa");
+        assert_eq!(decode_machine_code(&[0x3F]).to_string(), "aas");
+
+        let instruction: Instruction = decode_machine_code(&[0xD4, 0x0A]);
+        assert_eq!(instruction.length, 2);
+        assert_eq!(instruction.to_string(), "aam");
+
+        assert_eq!(decode_machine_code(&[0xD4, 0x10]).to_string(), "aam 16");
+        assert_eq!(decode_machine_code(&[0xD5, 0x0A]).to_string(), "aad");
+        assert_eq!(decode_machine_code(&[0xD5, 0x07]).to_string(), "aad 7");
+    }
+
+    #[test]
+    fn test_decode_in_out() {
+        assert_eq!(decode_machine_code(&[0xE4, 0x60]).to_string(), "in al, 96");
+        assert_eq!(decode_machine_code(&[0xE5, 0x60]).to_string(), "in ax, 96");
+        assert_eq!(decode_machine_code(&[0xE6, 0x61]).to_string(), "out 97, al");
+        assert_eq!(decode_machine_code(&[0xE7, 0x61]).to_string(), "out 97, ax");
+        assert_eq!(decode_machine_code(&[0xEC]).to_string(), "in al, dx");
+        assert_eq!(decode_machine_code(&[0xED]).to_string(), "in ax, dx");
+        assert_eq!(decode_machine_code(&[0xEE]).to_string(), "out dx, al");
+        assert_eq!(decode_machine_code(&[0xEF]).to_string(), "out dx, ax");
+        assert_eq!(decode_machine_code(&[0xE4, 0x60]).length, 2);
+        assert_eq!(decode_machine_code(&[0xEC]).length, 1);
+    }
+
+    #[test]
+    fn test_decode_interrupts() {
+        assert_eq!(decode_machine_code(&[0xCD, 0x21]).to_string(), "int 33");
+        assert_eq!(decode_machine_code(&[0xCD, 0x21]).length, 2);
+        assert_eq!(decode_machine_code(&[0xCC]).to_string(), "int3");
+        assert_eq!(decode_machine_code(&[0xCE]).to_string(), "into");
+        assert_eq!(decode_machine_code(&[0xCF]).to_string(), "iret");
+    }
+
+    #[test]
+    fn test_decode_flag_control() {
+        let mnemonics: Vec<String> = [0xF5, 0xF8, 0xF9, 0xFA, 0xFB, 0xFC, 0xFD].iter().map(|opcode: &u8| {
+            return decode_machine_code(&[*opcode]).to_string();
+        }).collect();
+        assert_eq!(mnemonics, ["cmc", "clc", "stc", "cli", "sti", "cld", "std"]);
+    }
+
+    #[test]
+    fn test_decode_stack() {
+        let instruction: Instruction = decode_machine_code(&[0x53]);
+        assert_eq!(instruction.operation, Operation::Push);
+        assert_eq!(instruction.to_string(), "push bx");
+
+        let instruction: Instruction = decode_machine_code(&[0x5C]);
+        assert_eq!(instruction.operation, Operation::Pop);
+        assert_eq!(instruction.to_string(), "pop sp");
+
+        let instruction: Instruction = decode_machine_code(&[0x0E]);
+        assert_eq!(instruction.to_string(), "push cs");
+
+        let instruction: Instruction = decode_machine_code(&[0x1F]);
+        assert_eq!(instruction.to_string(), "pop ds");
+
+        let instruction: Instruction = decode_machine_code(&[0xFF, 0x76, 0x04]);
+        assert_eq!(instruction.length, 3);
+        assert_eq!(instruction.to_string(), "push word [bp + 4]");
+
+        let instruction: Instruction = decode_machine_code(&[0x8F, 0x06, 0xE8, 0x03]);
+        assert_eq!(instruction.length, 4);
+        assert_eq!(instruction.to_string(), "pop word [1000]");
+
+        assert_eq!(decode_machine_code(&[0x9C]).to_string(), "pushf");
+        assert_eq!(decode_machine_code(&[0x9D]).to_string(), "popf");
+    }
+
+    #[test]
+    fn test_decode_jump() {
+        let instruction: Instruction = decode_machine_code(&[0x75, 0xFA]);
+        assert_eq!(instruction.operation, Operation::Jcc(0x5));
+        assert_eq!(instruction.to_string(), "jne $-4");
+        assert_eq!(instruction.destination, Some(Operand::Relative(-6)));
+        assert_eq!(instruction.length, 2);
+
+        let mnemonics: Vec<String> = (0x70..=0x7F).map(|opcode: u8| {
+            let instruction: Instruction = decode_machine_code(&[opcode, 0x00]);
+            return instruction.operation.mnemonic().to_string();
+        }).collect();
+        assert_eq!(mnemonics, [
+            "jo", "jno", "jb", "jnb", "je", "jne", "jbe", "ja", "js", "jns", "jp", "jnp", "jl", "jnl", "jle", "jg"
+        ]);
+    }
+
+    #[test]
+    fn test_decode_call_jump_return() {
+        let instruction: Instruction = decode_machine_code(&[0xE8, 0x00, 0x01]);
+        assert_eq!(instruction.operation, Operation::Call);
+        assert_eq!(instruction.destination, Some(Operand::Relative(256)));
+        assert_eq!(instruction.length, 3);
+
+        let instruction: Instruction = decode_machine_code(&[0xE9, 0xFD, 0xFF]);
+        assert_eq!(instruction.operation, Operation::Jmp);
+        assert_eq!(instruction.destination, Some(Operand::Relative(-3)));
+        assert_eq!(instruction.to_string(), "jmp near $+0");
+        assert_eq!(decode_machine_code(&[0xE9, 0x00, 0x00]).to_string(), "jmp near $+3");
+
+        let instruction: Instruction = decode_machine_code(&[0xEB, 0x02]);
+        assert_eq!(instruction.operation, Operation::Jmp);
+        assert_eq!(instruction.length, 2);
+        assert_eq!(instruction.to_string(), "jmp $+4");
+
+        assert_eq!(decode_machine_code(&[0xE2, 0xFE]).to_string(), "loop $+0");
+
+        let instruction: Instruction = decode_machine_code(&[0x9A, 0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(instruction.length, 5);
+        assert_eq!(instruction.to_string(), "call 4660:22136");
+
+        let instruction: Instruction = decode_machine_code(&[0xEA, 0x00, 0x00, 0xFF, 0xFF]);
+        assert_eq!(instruction.to_string(), "jmp 65535:0");
+
+        assert_eq!(decode_machine_code(&[0xFF, 0xD3]).to_string(), "call bx");
+        assert_eq!(decode_machine_code(&[0xFF, 0x17]).to_string(), "call word [bx]");
+        assert_eq!(decode_machine_code(&[0xFF, 0x1F]).to_string(), "call far [bx]");
+        assert_eq!(decode_machine_code(&[0xFF, 0x66, 0x02]).to_string(), "jmp word [bp + 2]");
+        assert_eq!(decode_machine_code(&[0xFF, 0x2E, 0xE8, 0x03]).to_string(), "jmp far [1000]");
+
+        assert_eq!(decode_machine_code(&[0xC3]).to_string(), "ret");
+        assert_eq!(decode_machine_code(&[0xC2, 0x04, 0x00]).to_string(), "ret 4");
+        assert_eq!(decode_machine_code(&[0xCB]).to_string(), "retf");
+        assert_eq!(decode_machine_code(&[0xCA, 0x06, 0x00]).to_string(), "retf 6");
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut memory = Memory::new();
+
+        memory.load(0, &[0x90, 0xD8]);
+        assert_eq!(decode(&memory, 0, 1), Err(Error::UnimplementedOpcode { opcode: 0xD8, address: SegmentedAddress::new(0, 1) }));
+
+        memory.load(0, &[0xC6, 0xC8, 0x05]);  // mov r/m8, imm8 only allows /0
+        assert_eq!(decode(&memory, 0, 0), Err(Error::InvalidModRm { opcode: 0xC6, mod_rm: 0xC8, address: SegmentedAddress::new(0, 0) }));
+
+        memory.load(0, &[0x8E, 0xE0]);  // there are only four segment registers
+        assert_eq!(decode(&memory, 0, 0), Err(Error::InvalidModRm { opcode: 0x8E, mod_rm: 0xE0, address: SegmentedAddress::new(0, 0) }));
+
+        memory.load(0, &[0xFE, 0x10]);  // 0xFE only has inc and dec
+        assert_eq!(decode(&memory, 0, 0), Err(Error::InvalidModRm { opcode: 0xFE, mod_rm: 0x10, address: SegmentedAddress::new(0, 0) }));
+
+        memory.load(0, &[0x8D, 0xC3]);  // there's no address of a register
+        assert_eq!(decode(&memory, 0, 0), Err(Error::InvalidModRm { opcode: 0x8D, mod_rm: 0xC3, address: SegmentedAddress::new(0, 0) }));
+
+        memory.load(0, &[0xFF, 0xD8]);  // far pointers can't come from a register
+        assert_eq!(decode(&memory, 0, 0), Err(Error::InvalidModRm { opcode: 0xFF, mod_rm: 0xD8, address: SegmentedAddress::new(0, 0) }));
+
+        memory.load(MEMORY_SIZE as u32 - 2, &[0xB8, 0x34]);  // mov ax, imm16 missing its high byte
+        assert_eq!(decode(&memory, 0xFFFF, 0x000E), Err(Error::FetchOutOfBounds { address: MEMORY_SIZE as u32 }));
+    }
+
+    #[test]
+    fn test_decode_prefix_limit() {
+        let mut memory = Memory::new();
+
+        // A segment of nothing but prefixes would otherwise wrap around its offset forever
+        memory.load(0x10000, &[CS_PREFIX; 0x10000]);
+        assert_eq!(decode(&memory, 0x1000, 0x0010), Err(Error::TooManyPrefixes { address: SegmentedAddress::new(0x1000, 0x0010) }));
+
+        memory.load(0x20000, &[LOCK_PREFIX; MAX_PREFIXES as usize]);
+        memory.load(0x20000 + MAX_PREFIXES as u32, &[0x90]);
+        let instruction: Instruction = decode(&memory, 0x2000, 0).expect("Failed to decode instruction");
+        assert_eq!(instruction.length, MAX_PREFIXES + 1);
+        assert_eq!(instruction.to_string(), "lock nop");
+    }
+}
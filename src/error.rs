@@ -0,0 +1,37 @@
+use crate::memory::SegmentedAddress;
+
+use std::fmt;
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    UnimplementedOpcode { opcode: u8, address: SegmentedAddress },
+    InvalidModRm { opcode: u8, mod_rm: u8, address: SegmentedAddress },
+    FetchOutOfBounds { address: u32 },
+    TooManyPrefixes { address: SegmentedAddress },
+    TraceFailed { kind: io::ErrorKind }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnimplementedOpcode { opcode, address } => {
+                return write!(f, "unimplemented opcode 0x{:02X} at {}", opcode, address);
+            },
+            Error::InvalidModRm { opcode, mod_rm, address } => {
+                return write!(f, "invalid ModRM byte 0x{:02X} for opcode 0x{:02X} at {}", mod_rm, opcode, address);
+            },
+            Error::FetchOutOfBounds { address } => {
+                return write!(f, "instruction fetch past the end of memory at physical address 0x{:05X}", address);
+            },
+            Error::TooManyPrefixes { address } => {
+                return write!(f, "too many prefixes on the instruction at {}", address);
+            },
+            Error::TraceFailed { kind } => {
+                return write!(f, "failed to write the trace: {}", kind);
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
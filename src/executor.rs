@@ -0,0 +1,93 @@
+use crate::registers::*;
+use crate::memory::*;
+use crate::ports::*;
+use crate::interrupt::*;
+use crate::instruction::*;
+use crate::data_transfer::*;
+use crate::arithmetic::*;
+use crate::bit_manipulation::*;
+use crate::string::*;
+use crate::control_transfer::*;
+use crate::processor_control::*;
+use crate::input_output::*;
+
+// Applies an already decoded instruction; ip must already point past the instruction
+pub fn execute(registers: &mut Registers, memory: &mut Memory, ports: &mut Ports, hooks: &mut InterruptHooks, instruction: &Instruction) {
+    match instruction.operation {
+        Operation::Mov => { execute_mov(registers, memory, instruction); },
+        Operation::Xchg => { execute_xchg(registers, memory, instruction); },
+        Operation::Nop => {},
+        Operation::Lea => { execute_lea(registers, instruction); },
+        Operation::Lds |
+        Operation::Les => { execute_load_far_pointer(registers, memory, instruction); },
+        Operation::Xlat => { execute_xlat(registers, memory, instruction); },
+        Operation::Lahf => { execute_lahf(registers); },
+        Operation::Sahf => { execute_sahf(registers); },
+        Operation::Cbw => { execute_cbw(registers); },
+        Operation::Cwd => { execute_cwd(registers); },
+        Operation::Add |
+        Operation::Or |
+        Operation::Adc |
+        Operation::Sbb |
+        Operation::And |
+        Operation::Sub |
+        Operation::Xor |
+        Operation::Cmp |
+        Operation::Test => { execute_arithmetic(registers, memory, instruction); },
+        Operation::Inc |
+        Operation::Dec => { execute_inc_dec(registers, memory, instruction); },
+        Operation::Daa |
+        Operation::Das => { execute_decimal_adjust(registers, instruction); },
+        Operation::Aaa |
+        Operation::Aas => { execute_ascii_adjust(registers, instruction); },
+        Operation::Aam => { execute_aam(registers, memory, hooks, instruction); },
+        Operation::Aad => { execute_aad(registers, instruction); },
+        Operation::Not => { execute_not(registers, memory, instruction); },
+        Operation::Neg => { execute_neg(registers, memory, instruction); },
+        Operation::Mul |
+        Operation::Imul => { execute_multiply(registers, memory, instruction); },
+        Operation::Div |
+        Operation::Idiv => { execute_divide(registers, memory, hooks, instruction); },
+        Operation::Rol |
+        Operation::Ror |
+        Operation::Rcl |
+        Operation::Rcr |
+        Operation::Shl |
+        Operation::Shr |
+        Operation::Sar => { execute_shift_rotate(registers, memory, instruction); },
+        Operation::Movs |
+        Operation::Cmps |
+        Operation::Scas |
+        Operation::Lods |
+        Operation::Stos => { execute_string(registers, memory, instruction); },
+        Operation::Jcc(condition) => { jcc(registers, instruction, condition); },
+        Operation::Loopnz => { loopnz(registers, instruction); },
+        Operation::Loopz => { loopz(registers, instruction); },
+        Operation::Loop => { loop_cx(registers, instruction); },
+        Operation::Jcxz => { jcxz(registers, instruction); },
+        Operation::Call => { call(registers, memory, instruction); },
+        Operation::CallFar => { call_far(registers, memory, instruction); },
+        Operation::Jmp => { jmp(registers, memory, instruction); },
+        Operation::JmpFar => { jmp_far(registers, memory, instruction); },
+        Operation::Ret => { ret(registers, memory, instruction); },
+        Operation::Retf => { retf(registers, memory, instruction); },
+        Operation::Push => { execute_push(registers, memory, instruction); },
+        Operation::Pop => { execute_pop(registers, memory, instruction); },
+        Operation::Pushf => { execute_pushf(registers, memory); },
+        Operation::Popf => { execute_popf(registers, memory); },
+        Operation::In => { execute_in(registers, memory, ports, instruction); },
+        Operation::Out => { execute_out(registers, memory, ports, instruction); },
+        Operation::Clc |
+        Operation::Stc |
+        Operation::Cmc |
+        Operation::Cld |
+        Operation::Std |
+        Operation::Cli |
+        Operation::Sti => { execute_flag_control(registers, instruction.operation); },
+        Operation::Int |
+        Operation::Int3 |
+        Operation::Into => { execute_interrupt(registers, memory, hooks, instruction); },
+        Operation::Iret => { execute_iret(registers, memory); },
+        Operation::Hlt => {}  // The machine's run loop stops on this
+    }
+}
@@ -0,0 +1,116 @@
+use crate::registers::*;
+use crate::memory::*;
+use crate::instruction::*;
+use crate::operand::*;
+use crate::ports::*;
+
+// The port is either an immediate byte or dx
+fn port_number(registers: &Registers, memory: &Memory, operand: Option<Operand>) -> u16 {
+    match operand {
+        Some(operand) => { return read_operand(registers, memory, operand, Width::Word); },
+        None => {
+            unreachable!("in and out always have a port operand");
+        }
+    }
+}
+
+pub fn execute_in(registers: &mut Registers, memory: &Memory, ports: &mut Ports, instruction: &Instruction) {
+    let port: u16 = port_number(registers, memory, instruction.source);
+    match instruction.width {
+        Width::Byte => {
+            let byte: u8 = ports.read_byte(port);
+            registers.ax = set_low_byte(registers.ax, byte);
+        },
+        Width::Word => { registers.ax = input_word(ports, port); }
+    }
+}
+
+pub fn execute_out(registers: &Registers, memory: &Memory, ports: &mut Ports, instruction: &Instruction) {
+    let port: u16 = port_number(registers, memory, instruction.destination);
+    match instruction.width {
+        Width::Byte => { ports.write_byte(port, get_low_byte(registers.ax)); },
+        Width::Word => { output_word(ports, port, registers.ax); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Answers every read with the next value and records every access
+    #[derive(Default)]
+    struct PortLog {
+        next_read: u8,
+        accesses: Vec<(char, u16, u8)>
+    }
+
+    impl PortDevice for PortLog {
+        fn read_byte(&mut self, port: u16) -> u8 {
+            self.next_read = self.next_read.wrapping_add(1);
+            self.accesses.push(('r', port, self.next_read));
+            return self.next_read;
+        }
+
+        fn write_byte(&mut self, port: u16, byte: u8) {
+            self.accesses.push(('w', port, byte));
+        }
+    }
+
+    #[test]
+    fn test_in_out() {
+        let machine_code: &[u8] = &[
+            0xE4, 0x60,             // in al, 96
+            0xBA, 0xF8, 0x03,       // mov dx, 1016
+            0xED,                   // in ax, dx
+            0xB8, 0x34, 0x12,       // mov ax, 0x1234
+            0xE6, 0x61,             // out 97, al
+            0xEF,                   // out dx, ax
+            0xEC                    // in al, dx
+        ];
+
+        let log: Rc<RefCell<PortLog>> = Rc::new(RefCell::new(PortLog::default()));
+
+        let mut machine = Machine::new();
+        machine.ports_mut().map(0x0060, 2, Box::new(log.clone()));
+        machine.ports_mut().map(0x03F8, 2, Box::new(log.clone()));
+        machine.load_program(machine_code);
+
+        machine.step().expect("Failed to step");
+        assert_eq!(machine.registers().ax, 0x0001);
+
+        machine.step().expect("Failed to step");
+        machine.step().expect("Failed to step");
+        assert_eq!(machine.registers().ax, 0x0302);
+
+        machine.run_until(machine.program_end());
+        assert_eq!(machine.registers().ax, 0x1204);
+
+        // Ports are relative to the start of the range they were mapped at
+        assert_eq!(log.borrow().accesses, vec![
+            ('r', 0, 0x01),
+            ('r', 0, 0x02),
+            ('r', 1, 0x03),
+            ('w', 1, 0x34),
+            ('w', 0, 0x34),
+            ('w', 1, 0x12),
+            ('r', 0, 0x04)
+        ]);
+    }
+
+    #[test]
+    fn test_unmapped_ports() {
+        let machine_code: &[u8] = &[
+            0xE6, 0x80,             // out 128, al
+            0xE5, 0x80              // in ax, 128
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+        machine.run_until(machine.program_end());
+        assert_eq!(machine.registers().ax, 0xFFFF);
+    }
+}
@@ -0,0 +1,328 @@
+pub use crate::operand::{Operand, MemoryOperand};
+use crate::registers::SEGMENT_REGISTER_ENCODINGS;
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    Byte,
+    Word
+}
+
+impl Width {
+    pub fn mask(self) -> u16 {
+        match self {
+            Width::Byte => { return 0x00FF; },
+            Width::Word => { return 0xFFFF; }
+        }
+    }
+
+    pub fn sign_bit(self) -> u16 {
+        match self {
+            Width::Byte => { return 0x0080; },
+            Width::Word => { return 0x8000; }
+        }
+    }
+}
+
+// Indexed by condition, each odd condition is the negation of the even one before it
+pub const CONDITIONAL_JUMP_MNEMONICS: &[&str; 16] = &[
+    "jo", "jno", "jb", "jnb", "je", "jne", "jbe", "ja", "js", "jns", "jp", "jnp", "jl", "jnl", "jle", "jg"
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Mov,
+    Xchg,
+    Nop,
+    Lea,
+    Lds,
+    Les,
+    Xlat,
+    Lahf,
+    Sahf,
+    Cbw,
+    Cwd,
+    Add,
+    Or,
+    Adc,
+    Sbb,
+    And,
+    Sub,
+    Xor,
+    Cmp,
+    Inc,
+    Dec,
+    Daa,
+    Das,
+    Aaa,
+    Aas,
+    Aam,
+    Aad,
+    Test,
+    Not,
+    Neg,
+    Mul,
+    Imul,
+    Div,
+    Idiv,
+    Rol,
+    Ror,
+    Rcl,
+    Rcr,
+    Shl,
+    Shr,
+    Sar,
+    Movs,
+    Cmps,
+    Scas,
+    Lods,
+    Stos,
+    Jcc(u8),  // Conditional jump, the condition is the low nibble of the opcode
+    Loopnz,
+    Loopz,
+    Loop,
+    Jcxz,
+    Call,
+    CallFar,
+    Jmp,
+    JmpFar,
+    Ret,
+    Retf,
+    Push,
+    Pop,
+    Pushf,
+    Popf,
+    In,
+    Out,
+    Clc,
+    Stc,
+    Cmc,
+    Cld,
+    Std,
+    Cli,
+    Sti,
+    Int,
+    Int3,
+    Into,
+    Iret,
+    Hlt
+}
+
+impl Operation {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Operation::Mov => { return "mov"; },
+            Operation::Xchg => { return "xchg"; },
+            Operation::Nop => { return "nop"; },
+            Operation::Lea => { return "lea"; },
+            Operation::Lds => { return "lds"; },
+            Operation::Les => { return "les"; },
+            Operation::Xlat => { return "xlatb"; },
+            Operation::Lahf => { return "lahf"; },
+            Operation::Sahf => { return "sahf"; },
+            Operation::Cbw => { return "cbw"; },
+            Operation::Cwd => { return "cwd"; },
+            Operation::Add => { return "add"; },
+            Operation::Or => { return "or"; },
+            Operation::Adc => { return "adc"; },
+            Operation::Sbb => { return "sbb"; },
+            Operation::And => { return "and"; },
+            Operation::Sub => { return "sub"; },
+            Operation::Xor => { return "xor"; },
+            Operation::Cmp => { return "cmp"; },
+            Operation::Inc => { return "inc"; },
+            Operation::Dec => { return "dec"; },
+            Operation::Daa => { return "daa"; },
+            Operation::Das => { return "das"; },
+            Operation::Aaa => { return "aaa"; },
+            Operation::Aas => { return "aas"; },
+            Operation::Aam => { return "aam"; },
+            Operation::Aad => { return "aad"; },
+            Operation::Test => { return "test"; },
+            Operation::Not => { return "not"; },
+            Operation::Neg => { return "neg"; },
+            Operation::Mul => { return "mul"; },
+            Operation::Imul => { return "imul"; },
+            Operation::Div => { return "div"; },
+            Operation::Idiv => { return "idiv"; },
+            Operation::Rol => { return "rol"; },
+            Operation::Ror => { return "ror"; },
+            Operation::Rcl => { return "rcl"; },
+            Operation::Rcr => { return "rcr"; },
+            Operation::Shl => { return "shl"; },
+            Operation::Shr => { return "shr"; },
+            Operation::Sar => { return "sar"; },
+            Operation::Movs => { return "movs"; },
+            Operation::Cmps => { return "cmps"; },
+            Operation::Scas => { return "scas"; },
+            Operation::Lods => { return "lods"; },
+            Operation::Stos => { return "stos"; },
+            Operation::Jcc(condition) => { return CONDITIONAL_JUMP_MNEMONICS[condition as usize]; },
+            Operation::Loopnz => { return "loopnz"; },
+            Operation::Loopz => { return "loopz"; },
+            Operation::Loop => { return "loop"; },
+            Operation::Jcxz => { return "jcxz"; },
+            Operation::Call | Operation::CallFar => { return "call"; },
+            Operation::Jmp | Operation::JmpFar => { return "jmp"; },
+            Operation::Ret => { return "ret"; },
+            Operation::Retf => { return "retf"; },
+            Operation::Push => { return "push"; },
+            Operation::Pop => { return "pop"; },
+            Operation::Pushf => { return "pushf"; },
+            Operation::Popf => { return "popf"; },
+            Operation::In => { return "in"; },
+            Operation::Out => { return "out"; },
+            Operation::Clc => { return "clc"; },
+            Operation::Stc => { return "stc"; },
+            Operation::Cmc => { return "cmc"; },
+            Operation::Cld => { return "cld"; },
+            Operation::Std => { return "std"; },
+            Operation::Cli => { return "cli"; },
+            Operation::Sti => { return "sti"; },
+            Operation::Int => { return "int"; },
+            Operation::Int3 => { return "int3"; },
+            Operation::Into => { return "into"; },
+            Operation::Iret => { return "iret"; },
+            Operation::Hlt => { return "hlt"; }
+        }
+    }
+
+    pub fn is_string(self) -> bool {
+        return matches!(self, Operation::Movs | Operation::Cmps | Operation::Scas | Operation::Lods | Operation::Stos);
+    }
+
+    pub fn is_shift_or_rotate(self) -> bool {
+        return matches!(self, Operation::Rol | Operation::Ror | Operation::Rcl | Operation::Rcr | Operation::Shl | Operation::Shr | Operation::Sar);
+    }
+}
+
+// Both prefixes repeat while CX is non zero. Only cmps and scas also check ZF, for them 0xF3 is
+// repe and 0xF2 is repne.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    Rep,
+    Repne
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Prefixes {
+    pub lock: bool,
+    pub segment: Option<u8>,
+    pub repeat: Option<Repeat>
+}
+
+impl Prefixes {
+    // For instructions with implicit memory operands, e.g. the source of a string instruction
+    pub fn segment_or(self, default_segment_field: u8) -> u8 {
+        return self.segment.unwrap_or(default_segment_field);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: u16,
+    pub length: u8,
+    pub opcode: u8,
+    pub operation: Operation,
+    pub width: Width,
+    pub destination: Option<Operand>,
+    pub source: Option<Operand>,
+    pub prefixes: Prefixes
+}
+
+impl Instruction {
+    pub fn new(opcode: u8, operation: Operation, width: Width) -> Self {
+        return Instruction {
+            address: 0,
+            length: 0,
+            opcode,
+            operation,
+            width,
+            destination: None,
+            source: None,
+            prefixes: Prefixes::default()
+        };
+    }
+
+    pub fn operands(&self) -> impl Iterator<Item = Operand> {
+        return self.destination.into_iter().chain(self.source);
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.prefixes.lock {
+            write!(f, "lock ")?;
+        }
+
+        if let Some(repeat) = self.prefixes.repeat {
+            let checks_zero: bool = matches!(self.operation, Operation::Cmps | Operation::Scas);
+            match (repeat, checks_zero) {
+                (Repeat::Rep, false) => { write!(f, "rep ")?; },
+                (Repeat::Rep, true) => { write!(f, "repe ")?; },
+                (Repeat::Repne, _) => { write!(f, "repne ")?; }
+            }
+        }
+
+        // A segment prefix is shown on the memory operand it applies to, if there is one
+        if let Some(segment_field) = self.prefixes.segment {
+            if !self.operands().any(|operand| matches!(operand, Operand::Memory(_))) {
+                write!(f, "{} ", SEGMENT_REGISTER_ENCODINGS[segment_field as usize])?;
+            }
+        }
+
+        write!(f, "{}", self.operation.mnemonic())?;
+
+        // String instructions have no operands to give their size
+        if self.operation.is_string() {
+            match self.width {
+                Width::Byte => { write!(f, "b")?; },
+                Width::Word => { write!(f, "w")?; }
+            }
+        }
+
+        // Without a register operand the assembler can't infer the size of a memory access. A shift
+        // count in cl says nothing about the size of what is shifted.
+        let has_register: bool = if self.operation.is_shift_or_rotate() {
+            self.destination.is_some_and(|operand| operand.is_register())
+        } else {
+            self.operands().any(|operand| operand.is_register())
+        };
+        let mut separator: &str = " ";
+        for operand in self.operands() {
+            write!(f, "{}", separator)?;
+            if !has_register && matches!(operand, Operand::Memory(_)) {
+                if matches!(self.operation, Operation::CallFar | Operation::JmpFar) {
+                    write!(f, "far ")?;
+                } else {
+                    match self.width {
+                        Width::Byte => { write!(f, "byte ")?; },
+                        Width::Word => { write!(f, "word ")?; }
+                    }
+                }
+            }
+
+            match operand {
+                // Relative to the start of the instruction so it reassembles to the same target
+                Operand::Relative(offset) => {
+                    // Otherwise NASM shrinks a near jmp with a close target to the two byte form
+                    if self.opcode == 0xE9 {
+                        write!(f, "near ")?;
+                    }
+
+                    let target: i32 = offset as i32 + self.length as i32;
+                    if target < 0 {
+                        write!(f, "$-{}", -target)?;
+                    } else {
+                        write!(f, "$+{}", target)?;
+                    }
+                },
+                _ => { write!(f, "{}", operand)?; }
+            }
+            separator = ", ";
+        }
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,190 @@
+use crate::registers::*;
+use crate::memory::*;
+use crate::instruction::*;
+use crate::operand::*;
+use crate::data_transfer::*;
+
+pub const DIVIDE_ERROR_VECTOR: u8 = 0;
+pub const BREAKPOINT_VECTOR: u8 = 3;
+pub const OVERFLOW_VECTOR: u8 = 4;
+
+const VECTOR_COUNT: usize = 256;
+
+// Runs in Rust in place of the guest's handler for a vector. ip already points past the
+// instruction which raised the interrupt and execution carries on from wherever ip is left.
+pub trait InterruptHandler {
+    fn handle(&mut self, registers: &mut Registers, memory: &mut Memory, vector: u8);
+}
+
+impl<F: FnMut(&mut Registers, &mut Memory, u8)> InterruptHandler for F {
+    fn handle(&mut self, registers: &mut Registers, memory: &mut Memory, vector: u8) {
+        self(registers, memory, vector);
+    }
+}
+
+// Host handlers by vector, every vector goes through the interrupt vector table by default
+pub struct InterruptHooks {
+    handlers: Vec<Option<Box<dyn InterruptHandler>>>
+}
+
+impl Default for InterruptHooks {
+    fn default() -> Self {
+        return InterruptHooks::new();
+    }
+}
+
+impl InterruptHooks {
+    pub fn new() -> Self {
+        return InterruptHooks { handlers: (0..VECTOR_COUNT).map(|_| None).collect() };
+    }
+
+    // Replaces any handler already hooked to the vector
+    pub fn hook(&mut self, vector: u8, handler: Box<dyn InterruptHandler>) {
+        self.handlers[vector as usize] = Some(handler);
+    }
+
+    pub fn unhook(&mut self, vector: u8) {
+        self.handlers[vector as usize] = None;
+    }
+
+    pub fn is_hooked(&self, vector: u8) -> bool {
+        return self.handlers[vector as usize].is_some();
+    }
+}
+
+// The interrupt vector table at the bottom of memory holds a far pointer to each handler
+pub fn interrupt_vector(memory: &Memory, vector: u8) -> SegmentedAddress {
+    let entry: SegmentedAddress = SegmentedAddress::new(0, vector as u16 * 4);
+    let offset: u16 = load_word(memory, entry);
+    let segment: u16 = load_word(memory, entry.offset_by(2));
+
+    return SegmentedAddress::new(segment, offset);
+}
+
+// Saves the flags and return address on the stack then enters the handler with interrupts and
+// single stepping disabled. ip must already point at the instruction to return to.
+pub fn raise_interrupt(registers: &mut Registers, memory: &mut Memory, vector: u8) {
+    let flags: u16 = registers.flags | FIXED_FLAG_BITS;
+    let return_segment: u16 = registers.cs;
+    let return_address: u16 = registers.ip;
+    push_word(registers, memory, flags);
+    push_word(registers, memory, return_segment);
+    push_word(registers, memory, return_address);
+
+    registers.flags &= !(IF_FLAG_BIT | TF_FLAG_BIT);
+
+    let handler: SegmentedAddress = interrupt_vector(memory, vector);
+    registers.cs = handler.segment;
+    registers.ip = handler.offset;
+}
+
+// Hooked vectors never touch the stack or the vector table
+pub fn dispatch_interrupt(registers: &mut Registers, memory: &mut Memory, hooks: &mut InterruptHooks, vector: u8) {
+    match &mut hooks.handlers[vector as usize] {
+        Some(handler) => { handler.handle(registers, memory, vector); },
+        None => { raise_interrupt(registers, memory, vector); }
+    }
+}
+
+pub fn execute_interrupt(registers: &mut Registers, memory: &mut Memory, hooks: &mut InterruptHooks, instruction: &Instruction) {
+    match (instruction.operation, instruction.destination) {
+        (Operation::Int, Some(Operand::Immediate(vector))) => { dispatch_interrupt(registers, memory, hooks, vector as u8); },
+        (Operation::Int3, _) => { dispatch_interrupt(registers, memory, hooks, BREAKPOINT_VECTOR); },
+        (Operation::Into, _) => {
+            if registers.flags & OF_FLAG_BIT != 0 {
+                dispatch_interrupt(registers, memory, hooks, OVERFLOW_VECTOR);
+            }
+        },
+        _ => {
+            unreachable!("{:?} is not an interrupt", instruction.operation);
+        }
+    }
+}
+
+// Undoes raise_interrupt, restoring the flags the handler was entered with
+pub fn execute_iret(registers: &mut Registers, memory: &mut Memory) {
+    registers.ip = pop_word(registers, memory);
+    registers.cs = pop_word(registers, memory);
+    registers.flags = pop_word(registers, memory) & DEFINED_FLAG_BITS;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, StopReason};
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_int_iret() {
+        let machine_code: &[u8] = &[
+            0xBC, 0x00, 0x10,       // mov sp, 0x1000
+            0xFB,                   // sti
+            0xF9,                   // stc
+            0xCD, 0x21,             // int 33
+            0xF4,                   // hlt
+            0xB9, 0x01, 0x00,       // mov cx, 1
+            0xF8,                   // clc
+            0xCF                    // iret
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program_at(0x0100, machine_code);
+        machine.load(0x21 * 4, &[0x08, 0x00, 0x00, 0x01]);    // 0x0100:0x0008
+
+        let handler: SegmentedAddress = SegmentedAddress::new(0x0100, 0x0008);
+        assert_eq!(machine.run_until(handler), StopReason::ReachedAddress(handler));
+        assert_eq!(machine.registers().flags, CF_FLAG_BIT);
+        assert_eq!(machine.registers().sp, 0x0FFA);
+        assert_eq!(load_word(machine.memory(), SegmentedAddress::new(0x0100, 0x0FFA)), 0x0007);
+        assert_eq!(load_word(machine.memory(), SegmentedAddress::new(0x0100, 0x0FFC)), 0x0100);
+        assert_eq!(load_word(machine.memory(), SegmentedAddress::new(0x0100, 0x0FFE)), FIXED_FLAG_BITS | IF_FLAG_BIT | CF_FLAG_BIT);
+
+        // The handler clears CF but iret restores the flags from before the interrupt
+        assert_eq!(machine.run(), StopReason::Halted);
+        assert_eq!(machine.registers().cx, 1);
+        assert_eq!(machine.registers().ip, 0x0008);
+        assert_eq!(machine.registers().sp, 0x1000);
+        assert_eq!(machine.registers().flags, IF_FLAG_BIT | CF_FLAG_BIT);
+    }
+
+    #[test]
+    fn test_hooked_interrupts() {
+        let machine_code: &[u8] = &[
+            0xCD, 0x10,             // int 16
+            0xCC,                   // int3
+            0xCE,                   // into
+            0xB0, 0x7F,             // mov al, 127
+            0x04, 0x01,             // add al, 1
+            0xCE,                   // into
+            0xF6, 0xF3,             // div bl
+            0xF4                    // hlt
+        ];
+
+        let raised: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        let handler = {
+            let raised: Rc<RefCell<Vec<u8>>> = raised.clone();
+            move |registers: &mut Registers, _memory: &mut Memory, vector: u8| {
+                raised.borrow_mut().push(vector);
+                if vector == 0x10 {
+                    registers.dx = 0xBEEF;
+                }
+            }
+        };
+
+        let mut machine = Machine::new();
+        for vector in [0x10, BREAKPOINT_VECTOR, OVERFLOW_VECTOR, DIVIDE_ERROR_VECTOR] {
+            machine.interrupt_hooks_mut().hook(vector, Box::new(handler.clone()));
+        }
+        machine.load_program_at(0x0100, machine_code);
+
+        // into does nothing while OF is clear and nothing goes through the stack or vector table
+        assert_eq!(machine.run(), StopReason::Halted);
+        assert_eq!(*raised.borrow(), vec![0x10, BREAKPOINT_VECTOR, OVERFLOW_VECTOR, DIVIDE_ERROR_VECTOR]);
+        assert_eq!(machine.registers().dx, 0xBEEF);
+        assert_eq!(machine.registers().cs, 0x0100);
+        assert_eq!(machine.registers().ip, machine_code.len() as u16);
+        assert_eq!(machine.registers().sp, 0);
+    }
+}
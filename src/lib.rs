@@ -0,0 +1,28 @@
+#![allow(clippy::needless_return)]
+
+pub mod registers;
+pub mod bus;
+pub mod memory;
+pub mod ports;
+pub mod instruction;
+pub mod operand;
+pub mod decoder;
+pub mod trace;
+pub mod error;
+pub mod interrupt;
+mod executor;
+mod data_transfer;
+mod arithmetic;
+mod bit_manipulation;
+mod string;
+mod control_transfer;
+mod processor_control;
+mod input_output;
+mod mode;
+mod machine;
+#[cfg(test)]
+mod test_helpers;
+
+pub use instruction::Instruction;
+pub use error::Error;
+pub use machine::{Machine, RunLimits, StopReason};
@@ -0,0 +1,390 @@
+use crate::registers::*;
+use crate::memory::*;
+use crate::ports::*;
+use crate::interrupt::*;
+use crate::instruction::*;
+use crate::decoder::*;
+use crate::executor::*;
+use crate::trace::*;
+use crate::error::*;
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Halted,
+    InstructionLimit,
+    ReachedAddress(SegmentedAddress),
+    Error(Error)
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Halted => { return write!(f, "halted"); },
+            StopReason::InstructionLimit => { return write!(f, "instruction limit reached"); },
+            StopReason::ReachedAddress(address) => { return write!(f, "reached {}", address); },
+            StopReason::Error(error) => { return write!(f, "{}", error); }
+        }
+    }
+}
+
+// Extra conditions on top of HLT and errors which end a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RunLimits {
+    pub max_instructions: Option<u64>,
+    pub stop_at: Option<SegmentedAddress>
+}
+
+pub struct Machine {
+    registers: Registers,
+    memory: Memory,
+    ports: Ports,
+    interrupt_hooks: InterruptHooks,
+    program_end: SegmentedAddress,
+    instruction_count: u64,
+    trace_sink: Box<dyn TraceSink>
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        return Machine::new();
+    }
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        return Machine {
+            registers: Registers::default(),
+            memory: Memory::new(),
+            ports: Ports::new(),
+            interrupt_hooks: InterruptHooks::new(),
+            program_end: SegmentedAddress::default(),
+            instruction_count: 0,
+            trace_sink: Box::new(NullTrace)
+        };
+    }
+
+    // Every executed instruction is reported to the sink, nothing is traced by default
+    pub fn set_trace_sink(&mut self, trace_sink: Box<dyn TraceSink>) {
+        self.trace_sink = trace_sink;
+    }
+
+    // Copies the program into memory at 0000:0000 and starts executing from there
+    pub fn load_program(&mut self, machine_code: &[u8]) {
+        self.load_program_at(0, machine_code);
+    }
+
+    // Copies the program to the start of the segment and points every segment register at it
+    pub fn load_program_at(&mut self, segment: u16, machine_code: &[u8]) {
+        self.load(SegmentedAddress::new(segment, 0).physical(), machine_code);
+        self.registers.cs = segment;
+        self.registers.ds = segment;
+        self.registers.es = segment;
+        self.registers.ss = segment;
+        self.registers.ip = 0;
+        self.program_end = SegmentedAddress::new(segment, machine_code.len() as u16);
+    }
+
+    // Copies bytes into memory starting at a physical address
+    pub fn load(&mut self, address: u32, bytes: &[u8]) {
+        self.memory.load(address, bytes);
+    }
+
+    // Decodes the instruction at the given offset in the code segment without executing it
+    pub fn decode(&self, address: u16) -> Result<Instruction, Error> {
+        return decode(&self.memory, self.registers.cs, address);
+    }
+
+    // An instruction which fails to decode is not executed and leaves ip pointing at it
+    pub fn step(&mut self) -> Result<Instruction, Error> {
+        let instruction: Instruction = decode(&self.memory, self.registers.cs, self.registers.ip)?;
+        self.registers.ip = self.registers.ip.wrapping_add(instruction.length as u16);
+        execute(&mut self.registers, &mut self.memory, &mut self.ports, &mut self.interrupt_hooks, &instruction);
+
+        self.instruction_count += 1;
+
+        // The instruction has already taken effect even if tracing it fails
+        if let Err(error) = self.trace_sink.trace(&instruction) {
+            return Err(Error::TraceFailed { kind: error.kind() });
+        }
+
+        return Ok(instruction);
+    }
+
+    // Runs until a HLT is executed, an error occurs or one of the limits is hit. Running again
+    // after a HLT carries on from the following instruction.
+    pub fn run_with_limits(&mut self, limits: RunLimits) -> StopReason {
+        let stop_reason: StopReason = self.run_until_stopped(limits);
+        match (self.trace_sink.flush(), stop_reason) {
+            (Err(error), StopReason::Halted | StopReason::InstructionLimit | StopReason::ReachedAddress(_)) => {
+                return StopReason::Error(Error::TraceFailed { kind: error.kind() });
+            },
+            _ => { return stop_reason; }
+        }
+    }
+
+    fn run_until_stopped(&mut self, limits: RunLimits) -> StopReason {
+        let mut executed: u64 = 0;
+        loop {
+            let address: SegmentedAddress = SegmentedAddress::new(self.registers.cs, self.registers.ip);
+            if limits.stop_at == Some(address) {
+                return StopReason::ReachedAddress(address);
+            }
+
+            if limits.max_instructions.is_some_and(|max_instructions| executed >= max_instructions) {
+                return StopReason::InstructionLimit;
+            }
+
+            match self.step() {
+                Ok(instruction) => {
+                    executed += 1;
+                    if instruction.operation == Operation::Hlt {
+                        return StopReason::Halted;
+                    }
+                },
+                Err(error) => {
+                    return StopReason::Error(error);
+                }
+            }
+        }
+    }
+
+    pub fn run(&mut self) -> StopReason {
+        return self.run_with_limits(RunLimits::default());
+    }
+
+    // Stops when CS:IP reaches the address, the same offset in another segment doesn't count
+    pub fn run_until(&mut self, address: SegmentedAddress) -> StopReason {
+        return self.run_with_limits(RunLimits { stop_at: Some(address), ..RunLimits::default() });
+    }
+
+    // The address just past the most recently loaded program
+    pub fn program_end(&self) -> SegmentedAddress {
+        return self.program_end;
+    }
+
+    // Total number of instructions executed since the machine was created
+    pub fn instruction_count(&self) -> u64 {
+        return self.instruction_count;
+    }
+
+    pub fn registers(&self) -> &Registers {
+        return &self.registers;
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        return &mut self.registers;
+    }
+
+    pub fn memory(&self) -> &Memory {
+        return &self.memory;
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        return &mut self.memory;
+    }
+
+    pub fn ports(&self) -> &Ports {
+        return &self.ports;
+    }
+
+    pub fn ports_mut(&mut self) -> &mut Ports {
+        return &mut self.ports;
+    }
+
+    // Vectors hooked here are handled in Rust instead of through the interrupt vector table
+    pub fn interrupt_hooks_mut(&mut self) -> &mut InterruptHooks {
+        return &mut self.interrupt_hooks;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::io;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_step_and_run_until() {
+        let machine_code: &[u8] = &[
+            0xB9, 0x03, 0x00,   // mov cx, 3
+            0xBB, 0xE8, 0x03,   // mov bx, 1000
+            0x83, 0xC3, 0x0A,   // add bx, 10
+            0x83, 0xE9, 0x01,   // sub cx, 1
+            0x75, 0xF8,         // jnz -8
+            0xF4                // hlt
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+
+        machine.step().expect("Failed to step");
+        assert_eq!(machine.registers().cx, 3);
+        assert_eq!(machine.registers().ip, 3);
+
+        assert_eq!(machine.run_until(SegmentedAddress::new(0, 12)), StopReason::ReachedAddress(SegmentedAddress::new(0, 12)));
+        assert_eq!(machine.registers().bx, 1010);
+        assert_eq!(machine.registers().cx, 2);
+
+        assert_eq!(machine.run(), StopReason::Halted);
+        assert_eq!(machine.registers().bx, 1030);
+        assert_eq!(machine.registers().cx, 0);
+        assert_eq!(machine.registers().ip, machine_code.len() as u16);
+        assert_eq!(machine.instruction_count(), 12);
+    }
+
+    #[test]
+    fn test_run_until_checks_segment() {
+        let machine_code: &[u8] = &[
+            0x9A, 0x00, 0x00, 0x00, 0x20,   // call 0x2000:0x0000
+            0x90                            // nop
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+        machine.load(0x20000, &[
+            0x90, 0x90, 0x90, 0x90, 0x90, 0x90, // nop x6
+            0xB9, 0x07, 0x00,                   // mov cx, 7
+            0xCB                                // retf
+        ]);
+
+        // The far routine passes offset 6 in its own segment without stopping the run
+        assert_eq!(machine.run_until(machine.program_end()), StopReason::ReachedAddress(SegmentedAddress::new(0, 6)));
+        assert_eq!(machine.registers().cx, 7);
+        assert_eq!(machine.instruction_count(), 1 + 6 + 2 + 1);
+    }
+
+    #[test]
+    fn test_instruction_limit() {
+        let machine_code: &[u8] = &[
+            0x83, 0xC0, 0x01,   // add ax, 1
+            0x75, 0xFB          // jnz -5
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+
+        let limits = RunLimits { max_instructions: Some(10), ..RunLimits::default() };
+        assert_eq!(machine.run_with_limits(limits), StopReason::InstructionLimit);
+        assert_eq!(machine.registers().ax, 5);
+        assert_eq!(machine.instruction_count(), 10);
+
+        let limits = RunLimits { max_instructions: Some(10), stop_at: Some(machine.program_end()) };
+        machine.registers_mut().ax = 0xFFFE;
+        assert_eq!(machine.run_with_limits(limits), StopReason::ReachedAddress(SegmentedAddress::new(0, 5)));
+        assert_eq!(machine.registers().ax, 0);
+    }
+
+    #[test]
+    fn test_collector_trace() {
+        let machine_code: &[u8] = &[
+            0xB9, 0x02, 0x00,   // mov cx, 2
+            0x83, 0xE9, 0x01,   // sub cx, 1
+            0x75, 0xFB,         // jnz -5
+            0xF4                // hlt
+        ];
+
+        let collector = CollectorTrace::new();
+
+        let mut machine = Machine::new();
+        machine.set_trace_sink(Box::new(collector.clone()));
+        machine.load_program(machine_code);
+        assert_eq!(machine.run(), StopReason::Halted);
+
+        assert_eq!(collector.lines(), vec![
+            "mov cx, 2",
+            "sub cx, 1",
+            "jne $-3",
+            "sub cx, 1",
+            "jne $-3",
+            "hlt"
+        ]);
+    }
+
+    // Counts how many times it has been flushed
+    #[derive(Clone, Default)]
+    struct FlushCounter {
+        flushes: Rc<Cell<u32>>
+    }
+
+    impl TraceSink for FlushCounter {
+        fn trace(&mut self, _instruction: &Instruction) -> io::Result<()> {
+            return Ok(());
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes.set(self.flushes.get() + 1);
+
+            return Ok(());
+        }
+    }
+
+    #[test]
+    fn test_trace_flushed_when_run_stops() {
+        let machine_code: &[u8] = &[
+            0xF4,               // hlt
+            0xD8                // esc
+        ];
+
+        let counter = FlushCounter::default();
+
+        let mut machine = Machine::new();
+        machine.set_trace_sink(Box::new(counter.clone()));
+        machine.load_program(machine_code);
+
+        machine.step().expect("Failed to step");
+        assert_eq!(counter.flushes.get(), 0);
+
+        assert!(matches!(machine.run(), StopReason::Error(_)));
+        assert_eq!(counter.flushes.get(), 1);
+    }
+
+    struct FailingTrace;
+
+    impl TraceSink for FailingTrace {
+        fn trace(&mut self, _instruction: &Instruction) -> io::Result<()> {
+            return Err(io::Error::from(io::ErrorKind::WriteZero));
+        }
+    }
+
+    #[test]
+    fn test_trace_failure_stops_run() {
+        let machine_code: &[u8] = &[
+            0xB9, 0x03, 0x00,   // mov cx, 3
+            0xF4                // hlt
+        ];
+
+        let mut machine = Machine::new();
+        machine.set_trace_sink(Box::new(FailingTrace));
+        machine.load_program(machine_code);
+
+        // The instruction still ran, only reporting it failed
+        assert_eq!(machine.run(), StopReason::Error(Error::TraceFailed { kind: io::ErrorKind::WriteZero }));
+        assert_eq!(machine.registers().cx, 3);
+        assert_eq!(machine.registers().ip, 3);
+    }
+
+    #[test]
+    fn test_step_error() {
+        let machine_code: &[u8] = &[
+            0xB9, 0x03, 0x00,   // mov cx, 3
+            0x11, 0xC8,         // adc ax, cx
+            0xD8                // esc
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+
+        assert_eq!(machine.run(), StopReason::Error(Error::UnimplementedOpcode { opcode: 0xD8, address: SegmentedAddress::new(0, 5) }));
+        assert_eq!(machine.registers().ip, 5);
+        assert_eq!(machine.registers().ax, 3);
+        assert_eq!(machine.instruction_count(), 2);
+
+        // Stepping again hits the same error without moving on
+        assert_eq!(machine.step(), Err(Error::UnimplementedOpcode { opcode: 0xD8, address: SegmentedAddress::new(0, 5) }));
+        assert_eq!(machine.registers().ip, 5);
+    }
+}
@@ -1,363 +1,184 @@
-mod registers;
-mod memory;
-mod data_transfer;
-mod arithmetic;
-mod control_transfer;
-mod mode;
+#![allow(clippy::needless_return)]
 
-use registers::*;
-use memory::*;
-use data_transfer::*;
-use arithmetic::*;
-use control_transfer::*;
+use emulator_8086::{Machine, RunLimits, StopReason};
+use emulator_8086::trace::*;
+use emulator_8086::memory::SegmentedAddress;
 
 use std::env;
 use std::fs;
+use std::convert::TryFrom;
+use std::process::ExitCode;
 
-fn unimplemented_op(_registers: &mut Registers, _memory: &mut Memory) {
-    debug_assert!(false);
-}
-
-type Op = fn(&mut Registers, &mut Memory);
-const OPS: &'static [Op; 256] = &[
-    // 0x00
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_imm_to_acc_8_bit,
-    arithmetic_imm_to_acc_16_bit,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x08
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_imm_to_acc_8_bit,
-    arithmetic_imm_to_acc_16_bit,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x10
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_imm_to_acc_8_bit,
-    arithmetic_imm_to_acc_16_bit,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x18
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_imm_to_acc_8_bit,
-    arithmetic_imm_to_acc_16_bit,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x20
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_imm_to_acc_8_bit,
-    arithmetic_imm_to_acc_16_bit,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x28
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_imm_to_acc_8_bit,
-    arithmetic_imm_to_acc_16_bit,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x30
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_imm_to_acc_8_bit,
-    arithmetic_imm_to_acc_16_bit,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x38
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_mem_reg_with_reg_to_either_8_bit,
-    arithmetic_mem_reg_with_reg_to_either_16_bit,
-    arithmetic_imm_to_acc_8_bit,
-    arithmetic_imm_to_acc_16_bit,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x40
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x48
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x50
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x58
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x60
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x68
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x70
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    je,
-    jne,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x78
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x80
-    arithmetic_imm_to_reg_mem_8_bit,
-    arithmetic_imm_to_reg_mem_16_bit,
-    arithmetic_imm_to_reg_mem_8_bit,
-    arithmetic_imm_to_reg_mem_16_bit,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x88
-    mov_mem_reg_to_from_reg_8_bit,
-    mov_mem_reg_to_from_reg_16_bit,
-    mov_mem_reg_to_from_reg_8_bit,
-    mov_mem_reg_to_from_reg_16_bit,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
+const USAGE: &str = "Usage: emulator_8086 [--trace stdout|none] [--trace-file <path>] [--max-instructions <count>] [--until [<segment>:]<offset>] <input file>\n\
+    Execution stops on hlt, an error, the instruction limit or reaching the --until address, which defaults to the end of the program.";
 
-    // 0x90
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0x98
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0xA0
-    mov_mem_to_acc_8_bit,
-    mov_mem_to_acc_16_bit,
-    mov_acc_to_mem_8_bit,
-    mov_acc_to_mem_16_bit,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0xA8
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-
-    // 0xB0
-    mov_imm_to_reg_8_bit,
-    mov_imm_to_reg_8_bit,
-    mov_imm_to_reg_8_bit,
-    mov_imm_to_reg_8_bit,
-    mov_imm_to_reg_8_bit,
-    mov_imm_to_reg_8_bit,
-    mov_imm_to_reg_8_bit,
-    mov_imm_to_reg_8_bit,
+enum TraceOption {
+    Stdout,
+    Null,
+    File(String)
+}
 
-    // 0xB8
-    mov_imm_to_reg_16_bit,
-    mov_imm_to_reg_16_bit,
-    mov_imm_to_reg_16_bit,
-    mov_imm_to_reg_16_bit,
-    mov_imm_to_reg_16_bit,
-    mov_imm_to_reg_16_bit,
-    mov_imm_to_reg_16_bit,
-    mov_imm_to_reg_16_bit,
+struct Options {
+    input_file: String,
+    trace: TraceOption,
+    max_instructions: Option<u64>,
+    until: Option<SegmentedAddress>
+}
 
-    // 0xC0
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    mov_imm_to_reg_mem_8_bit,
-    mov_imm_to_reg_mem_16_bit,
+// Accepts decimal or 0x prefixed hexadecimal
+fn parse_number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => { return u64::from_str_radix(hex, 16).ok(); },
+        None => { return text.parse().ok(); }
+    }
+}
 
-    // 0xC8
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
+fn parse_word(text: &str) -> Option<u16> {
+    return parse_number(text).and_then(|number| u16::try_from(number).ok());
+}
 
-    // 0xD0
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
+// segment:offset, or just an offset in the segment the program is loaded at
+fn parse_address(text: &str) -> Option<SegmentedAddress> {
+    match text.split_once(':') {
+        Some((segment, offset)) => { return Some(SegmentedAddress::new(parse_word(segment)?, parse_word(offset)?)); },
+        None => { return Some(SegmentedAddress::new(0, parse_word(text)?)); }
+    }
+}
 
-    // 0xD8
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut input_file: Option<String> = None;
+    let mut trace = TraceOption::Stdout;
+    let mut max_instructions: Option<u64> = None;
+    let mut until: Option<SegmentedAddress> = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace" => {
+                match args.next().as_deref() {
+                    Some("stdout") => { trace = TraceOption::Stdout; },
+                    Some("none") => { trace = TraceOption::Null; },
+                    Some(other) => { return Err(format!("Unknown trace sink '{}'", other)); },
+                    None => { return Err(String::from("--trace needs a value")); }
+                }
+            },
+            "--trace-file" => {
+                let path: String = args.next().ok_or_else(|| String::from("--trace-file needs a path"))?;
+                trace = TraceOption::File(path);
+            },
+            "--max-instructions" => {
+                let count: Option<u64> = args.next().as_deref().and_then(parse_number);
+                max_instructions = Some(count.ok_or_else(|| String::from("--max-instructions needs a count"))?);
+            },
+            "--until" => {
+                let address: Option<SegmentedAddress> = args.next().as_deref().and_then(parse_address);
+                until = Some(address.ok_or_else(|| String::from("--until needs an address"))?);
+            },
+            _ if arg.starts_with('-') => {
+                return Err(format!("Unknown option '{}'", arg));
+            },
+            _ => {
+                if input_file.is_some() {
+                    return Err(format!("Unexpected argument '{}'", arg));
+                }
+
+                input_file = Some(arg);
+            }
+        }
+    }
 
-    // 0xE0
-    loopnz,
-    loopz,
-    loop_cx,
-    jcxz,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
+    let input_file: String = input_file.ok_or_else(|| String::from("Please specify an input file"))?;
 
-    // 0xE8
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
+    return Ok(Options { input_file, trace, max_instructions, until });
+}
 
-    // 0xF0
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
+// Returns rather than calling process::exit so the machine and its trace sink are dropped properly
+fn main() -> ExitCode {
+    let options: Options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let machine_code: Vec<u8> = match fs::read(&options.input_file) {
+        Ok(machine_code) => machine_code,
+        Err(error) => {
+            eprintln!("error: failed to read '{}': {}", options.input_file, error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut machine = Machine::new();
+    machine.load_program(&machine_code);
+
+    match options.trace {
+        TraceOption::Stdout => {
+            match StdoutTrace::new() {
+                Ok(stdout_trace) => { machine.set_trace_sink(Box::new(stdout_trace)); },
+                Err(error) => {
+                    eprintln!("error: failed to write the trace: {}", error);
+                    return ExitCode::FAILURE;
+                }
+            }
+        },
+        TraceOption::Null => {
+            machine.set_trace_sink(Box::new(NullTrace));
+        },
+        TraceOption::File(path) => {
+            match FileTrace::create(&path) {
+                Ok(file_trace) => { machine.set_trace_sink(Box::new(file_trace)); },
+                Err(error) => {
+                    eprintln!("error: failed to create trace file '{}': {}", path, error);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+    }
 
-    // 0xF8
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op,
-    unimplemented_op
-];
+    let limits = RunLimits {
+        max_instructions: options.max_instructions,
+        stop_at: Some(options.until.unwrap_or(machine.program_end()))
+    };
+    let stop_reason: StopReason = machine.run_with_limits(limits);
 
-fn main() {
-    let input_file = env::args().nth(1).expect("Please specify an input file");
-    let machine_code: Vec<u8> = fs::read(input_file).expect("Missing instruction stream file");
+    println!("; stopped after {} instructions: {}", machine.instruction_count(), stop_reason);
+    for line in machine.registers().to_string().lines() {
+        println!("; {}", line);
+    }
 
-    let mut registers = Registers::default();
+    if let StopReason::Error(error) = stop_reason {
+        eprintln!("error: {}", error);
+        return ExitCode::FAILURE;
+    }
 
-    let mut memory: Memory = [0; u16::MAX as usize];
-    memory[0..machine_code.len()].copy_from_slice(&machine_code);
+    return ExitCode::SUCCESS;
+}
 
-    println!("bits 16");
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let byte_count: usize = machine_code.len();
-    while (registers.ip as usize) < byte_count {
-        let byte: u8 = machine_code[registers.ip as usize];
-        let op: Op = OPS[byte as usize];
-        op(&mut registers, &mut memory);
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        return parse_args(args.iter().map(|arg| arg.to_string()));
     }
 
-    dbg!(registers);
+    #[test]
+    fn test_parse_args() {
+        let options: Options = parse(&["--max-instructions", "0x10", "--trace", "none", "program.bin"]).expect("Failed to parse");
+        assert_eq!(options.input_file, "program.bin");
+        assert_eq!(options.max_instructions, Some(16));
+        assert!(matches!(options.trace, TraceOption::Null));
+
+        let options: Options = parse(&["--until", "0x1000:0x20", "program.bin"]).expect("Failed to parse");
+        assert_eq!(options.until, Some(SegmentedAddress::new(0x1000, 0x0020)));
+        let options: Options = parse(&["--until", "32", "program.bin"]).expect("Failed to parse");
+        assert_eq!(options.until, Some(SegmentedAddress::new(0, 0x0020)));
+        assert!(parse(&["--until", "0x10000", "program.bin"]).is_err());
+
+        assert_eq!(parse(&["--bogus", "program.bin"]).err(), Some(String::from("Unknown option '--bogus'")));
+        assert_eq!(parse(&["program.bin", "other.bin"]).err(), Some(String::from("Unexpected argument 'other.bin'")));
+        assert!(parse(&["--trace", "file"]).is_err());
+        assert!(parse(&[]).is_err());
+    }
 }
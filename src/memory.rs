@@ -1,50 +1,211 @@
-pub type Memory = [u8; u16::MAX as usize];
+use crate::bus::*;
+use crate::error::*;
 
-pub fn store_byte(memory: &mut Memory, address: u16, byte: u8) {
-    debug_assert!((address as usize) < memory.len());
+use std::fmt;
 
-    memory[address as usize] = byte;
+pub const MEMORY_SIZE: usize = 1 << 20;
+const ADDRESS_MASK: u32 = MEMORY_SIZE as u32 - 1;
+
+struct MappedRegion {
+    start: u32,
+    length: u32,
+    device: Box<dyn Bus>
+}
+
+impl MappedRegion {
+    fn contains(&self, address: u32) -> bool {
+        return address >= self.start && address - self.start < self.length;
+    }
+}
+
+// The physical address space the CPU reads and writes through. It is RAM everywhere apart from
+// the regions devices have been mapped over.
+pub struct Memory {
+    ram: Ram,
+    regions: Vec<MappedRegion>
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        return Memory::new();
+    }
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        return Memory { ram: Ram::new(MEMORY_SIZE), regions: Vec::new() };
+    }
+
+    // Later mappings take precedence where regions overlap
+    pub fn map(&mut self, start: u32, length: u32, device: Box<dyn Bus>) {
+        assert!(start as usize + length as usize <= MEMORY_SIZE, "mapped region must fit in the address space");
+        self.regions.push(MappedRegion { start, length, device });
+    }
+
+    pub fn map_rom(&mut self, start: u32, bytes: &[u8]) {
+        self.map(start, bytes.len() as u32, Box::new(Rom::new(bytes)));
+    }
+
+    // Copies bytes in through the bus starting at a physical address
+    pub fn load(&mut self, address: u32, bytes: &[u8]) {
+        for (index, byte) in bytes.iter().enumerate() {
+            self.write_byte(address.wrapping_add(index as u32), *byte);
+        }
+    }
+}
+
+impl Bus for Memory {
+    fn read_byte(&self, address: u32) -> u8 {
+        let address: u32 = address & ADDRESS_MASK;
+        match self.regions.iter().rev().find(|region| region.contains(address)) {
+            Some(region) => { return region.device.read_byte(address - region.start); },
+            None => { return self.ram.read_byte(address); }
+        }
+    }
+
+    fn write_byte(&mut self, address: u32, byte: u8) {
+        let address: u32 = address & ADDRESS_MASK;
+        match self.regions.iter_mut().rev().find(|region| region.contains(address)) {
+            Some(region) => { region.device.write_byte(address - region.start, byte); },
+            None => { self.ram.write_byte(address, byte); }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SegmentedAddress {
+    pub segment: u16,
+    pub offset: u16
+}
+
+impl SegmentedAddress {
+    pub fn new(segment: u16, offset: u16) -> Self {
+        return SegmentedAddress { segment, offset };
+    }
+
+    // Addresses past the top of memory wrap around to the bottom like on the 8086
+    pub fn physical(self) -> u32 {
+        return (((self.segment as u32) << 4) + self.offset as u32) & ADDRESS_MASK;
+    }
+
+    // Offsets wrap within the segment
+    pub fn offset_by(self, displacement: u16) -> Self {
+        return SegmentedAddress { segment: self.segment, offset: self.offset.wrapping_add(displacement) };
+    }
+}
+
+impl fmt::Display for SegmentedAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{:04X}:{:04X}", self.segment, self.offset);
+    }
 }
 
-pub fn store_word(memory: &mut Memory, address: u16, word: u16) {
-    debug_assert!((address as usize + 1) < memory.len());
+pub fn store_byte(memory: &mut Memory, address: SegmentedAddress, byte: u8) {
+    memory.write_byte(address.physical(), byte);
+}
 
+pub fn store_word(memory: &mut Memory, address: SegmentedAddress, word: u16) {
     let word_low: u8 = (word & 0x00FF) as u8;
-    memory[address as usize] = word_low;
+    store_byte(memory, address, word_low);
 
     let word_high: u8 = ((word & 0xFF00) >> 8) as u8;
-    memory[address as usize + 1] = word_high;
+    store_byte(memory, address.offset_by(1), word_high);
 }
 
-pub fn load_byte(memory: &Memory, address: u16) -> u8 {
-    debug_assert!((address as usize) < memory.len());
+pub fn load_byte(memory: &Memory, address: SegmentedAddress) -> u8 {
+    let byte: u8 = memory.read_byte(address.physical());
 
-    let byte: u8 = memory[address as usize];
-    
     return byte;
 }
 
-pub fn load_word(memory: &Memory, address: u16) -> u16 {
-    debug_assert!((address as usize + 1) < memory.len());
-
-    let word_low: u8 = memory[address as usize];
-    let word_high: u8 = memory[address as usize + 1];
+pub fn load_word(memory: &Memory, address: SegmentedAddress) -> u16 {
+    let word_low: u8 = load_byte(memory, address);
+    let word_high: u8 = load_byte(memory, address.offset_by(1));
 
     let word: u16 = ((word_high as u16) << 8) + (word_low as u16);
 
     return word;
 }
 
-pub fn grab_instruction_byte(memory: &Memory, ip: &mut u16) -> u8 {
-    let byte: u8 = load_byte(memory, *ip);
-    *ip += 1;
-    
-    return byte;
+// Unlike data accesses, running code off the top of memory is treated as an error rather than
+// silently wrapping around to the interrupt vector table
+pub fn peek_instruction_byte(memory: &Memory, ip: SegmentedAddress) -> Result<u8, Error> {
+    let unwrapped_address: u32 = ((ip.segment as u32) << 4) + ip.offset as u32;
+    if unwrapped_address as usize >= MEMORY_SIZE {
+        return Err(Error::FetchOutOfBounds { address: unwrapped_address });
+    }
+
+    let byte: u8 = load_byte(memory, ip);
+
+    return Ok(byte);
 }
 
-pub fn grab_instruction_word(memory: &Memory, ip: &mut u16) -> u16 {
-    let word: u16 = load_word(memory, *ip);
-    *ip += 2;
+pub fn grab_instruction_byte(memory: &Memory, ip: &mut SegmentedAddress) -> Result<u8, Error> {
+    let byte: u8 = peek_instruction_byte(memory, *ip)?;
+    *ip = ip.offset_by(1);
 
-    return word;
+    return Ok(byte);
+}
+
+pub fn grab_instruction_word(memory: &Memory, ip: &mut SegmentedAddress) -> Result<u16, Error> {
+    let word_low: u8 = grab_instruction_byte(memory, ip)?;
+    let word_high: u8 = grab_instruction_byte(memory, ip)?;
+
+    let word: u16 = ((word_high as u16) << 8) + (word_low as u16);
+
+    return Ok(word);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_segmented_address() {
+        assert_eq!(SegmentedAddress::new(0x1234, 0x5678).physical(), 0x179B8);
+        assert_eq!(SegmentedAddress::new(0xFFFF, 0x0010).physical(), 0x00000);
+        assert_eq!(SegmentedAddress::new(0x2000, 0xFFFF).offset_by(2), SegmentedAddress::new(0x2000, 0x0001));
+
+        let mut memory = Memory::new();
+        store_word(&mut memory, SegmentedAddress::new(0x2000, 0xFFFF), 0xBEEF);
+        assert_eq!(memory.read_byte(0x2FFFF), 0xEF);
+        assert_eq!(memory.read_byte(0x20000), 0xBE);
+        assert_eq!(load_word(&memory, SegmentedAddress::new(0x2000, 0xFFFF)), 0xBEEF);
+    }
+
+    #[test]
+    fn test_mapped_regions() {
+        let mut memory = Memory::new();
+        memory.map_rom(0xF0000, &[0x12, 0x34]);
+
+        let video_buffer: Rc<RefCell<Ram>> = Rc::new(RefCell::new(Ram::new(0x1000)));
+        memory.map(0xB8000, 0x1000, Box::new(video_buffer.clone()));
+
+        // ROM ignores writes
+        store_word(&mut memory, SegmentedAddress::new(0xF000, 0), 0xFFFF);
+        assert_eq!(load_word(&memory, SegmentedAddress::new(0xF000, 0)), 0x3412);
+
+        // Device addresses are relative to the start of the region
+        store_byte(&mut memory, SegmentedAddress::new(0xB800, 2), b'A');
+        assert_eq!(video_buffer.borrow().bytes()[2], b'A');
+        assert_eq!(memory.read_byte(0xB8002), b'A');
+
+        // Everything unmapped is still RAM
+        store_byte(&mut memory, SegmentedAddress::new(0xB900, 0), 0x56);
+        assert_eq!(memory.read_byte(0xB9000), 0x56);
+    }
+
+    #[test]
+    fn test_region_larger_than_device() {
+        let mut memory = Memory::new();
+        memory.map(0xB8000, 0x2000, Box::new(Ram::new(0x1000)));
+        memory.map(0xF0000, 0x0010, Box::new(Rom::new(&[0x12, 0x34])));
+
+        store_byte(&mut memory, SegmentedAddress::new(0xB800, 0x1800), b'A');
+        assert_eq!(load_byte(&memory, SegmentedAddress::new(0xB800, 0x1800)), 0xFF);
+        assert_eq!(load_word(&memory, SegmentedAddress::new(0xF000, 0x0001)), 0xFF34);
+    }
 }
@@ -0,0 +1,203 @@
+use crate::registers::*;
+use crate::memory::*;
+use crate::mode::*;
+use crate::instruction::*;
+use crate::error::*;
+
+use std::fmt;
+
+// A memory reference as encoded by the mod and r/m fields. A missing expression means the
+// displacement is a direct address. The segment override is set by a segment prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryOperand {
+    pub expression: Option<u8>,
+    pub displacement: u16,
+    pub segment_override: Option<u8>
+}
+
+impl MemoryOperand {
+    pub fn direct(address: u16) -> Self {
+        return MemoryOperand { expression: None, displacement: address, segment_override: None };
+    }
+
+    pub fn indirect(expression_index: u8, displacement: u16) -> Self {
+        return MemoryOperand { expression: Some(expression_index), displacement, segment_override: None };
+    }
+
+    // The segment:offset address referenced, using the override or else the segment the expression implies
+    pub fn address(&self, registers: &Registers) -> SegmentedAddress {
+        let default_address: SegmentedAddress = match self.expression {
+            None => SegmentedAddress::new(registers.ds, self.displacement),
+            Some(expression_index) => {
+                let reg_expression: SegmentedAddress = calculate_reg_expression(registers, expression_index);
+                reg_expression.offset_by(self.displacement)
+            }
+        };
+
+        match self.segment_override {
+            Some(segment_field) => {
+                return SegmentedAddress::new(get_segment_register(registers, segment_field), default_address.offset);
+            },
+            None => { return default_address; }
+        }
+    }
+
+    // Just the offset part of the address
+    pub fn effective_address(&self, registers: &Registers) -> u16 {
+        return self.address(registers).offset;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register8(u8),
+    Register16(u8),
+    SegmentRegister(u8),
+    Memory(MemoryOperand),
+    Immediate(u16),
+    Relative(i16),
+    FarAddress { segment: u16, offset: u16 }
+}
+
+impl Operand {
+    pub fn register(field_index: u8, width: Width) -> Self {
+        match width {
+            Width::Byte => { return Operand::Register8(field_index); },
+            Width::Word => { return Operand::Register16(field_index); }
+        }
+    }
+
+    pub fn is_register(&self) -> bool {
+        return matches!(self, Operand::Register8(_) | Operand::Register16(_) | Operand::SegmentRegister(_));
+    }
+
+    // Only memory operands are affected by a segment prefix
+    pub fn with_segment_override(self, segment_field: u8) -> Self {
+        match self {
+            Operand::Memory(memory_operand) => {
+                return Operand::Memory(MemoryOperand { segment_override: Some(segment_field), ..memory_operand });
+            },
+            _ => { return self; }
+        }
+    }
+}
+
+// The decoded mod reg r/m byte. What the reg field selects depends on the instruction so it is
+// left for the caller to interpret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModRm {
+    pub byte: u8,
+    pub reg_field: u8,
+    pub operand: Operand
+}
+
+// Reads the mod reg r/m byte and any displacement following it
+pub fn decode_mod_rm(memory: &Memory, ip: &mut SegmentedAddress, width: Width) -> Result<ModRm, Error> {
+    let byte: u8 = grab_instruction_byte(memory, ip)?;
+
+    let mod_field: u8 = (byte & 0xC0) >> 6;
+    let reg_field: u8 = (byte & 0x38) >> 3;
+    let rm_field: u8 = byte & 0x07;
+
+    let operand: Operand = match mod_field {
+        MODE_MEM_NO_DISP => {
+            if rm_field == 6 {
+                let address: u16 = grab_instruction_word(memory, ip)?;
+                Operand::Memory(MemoryOperand::direct(address))
+            } else {
+                Operand::Memory(MemoryOperand::indirect(rm_field, 0))
+            }
+        },
+        MODE_MEM_8_BIT_DISP => {
+            let displacement: i8 = grab_instruction_byte(memory, ip)? as i8;
+            Operand::Memory(MemoryOperand::indirect(rm_field, displacement as u16))
+        },
+        MODE_MEM_16_BIT_DISP => {
+            let displacement: u16 = grab_instruction_word(memory, ip)?;
+            Operand::Memory(MemoryOperand::indirect(rm_field, displacement))
+        },
+        MODE_REG => {
+            Operand::register(rm_field, width)
+        },
+        _ => {
+            unreachable!("mod field is only two bits");
+        }
+    };
+
+    return Ok(ModRm { byte, reg_field, operand });
+}
+
+// Byte sized values are held in the low byte of the result
+pub fn read_operand(registers: &Registers, memory: &Memory, operand: Operand, width: Width) -> u16 {
+    match operand {
+        Operand::Register8(field_index) => { return get_8_bit_register(registers, field_index) as u16; },
+        Operand::Register16(field_index) => { return get_16_bit_register(registers, field_index); },
+        Operand::SegmentRegister(field_index) => { return get_segment_register(registers, field_index); },
+        Operand::Memory(memory_operand) => {
+            let address: SegmentedAddress = memory_operand.address(registers);
+            match width {
+                Width::Byte => { return load_byte(memory, address) as u16; },
+                Width::Word => { return load_word(memory, address); }
+            }
+        },
+        Operand::Immediate(immediate) => { return immediate & width.mask(); },
+        Operand::Relative(_) | Operand::FarAddress { .. } => {
+            unreachable!("{:?} is only used as a jump target", operand);
+        }
+    }
+}
+
+pub fn write_operand(registers: &mut Registers, memory: &mut Memory, operand: Operand, width: Width, value: u16) {
+    match operand {
+        Operand::Register8(field_index) => { set_8_bit_register(registers, field_index, value as u8); },
+        Operand::Register16(field_index) => { set_16_bit_register(registers, field_index, value); },
+        Operand::SegmentRegister(field_index) => { set_segment_register(registers, field_index, value); },
+        Operand::Memory(memory_operand) => {
+            let address: SegmentedAddress = memory_operand.address(registers);
+            match width {
+                Width::Byte => { store_byte(memory, address, value as u8); },
+                Width::Word => { store_word(memory, address, value); }
+            }
+        },
+        Operand::Immediate(_) | Operand::Relative(_) | Operand::FarAddress { .. } => {
+            unreachable!("decoder never produces a write to {:?}", operand);
+        }
+    }
+}
+
+impl fmt::Display for MemoryOperand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(segment_field) = self.segment_override {
+            write!(f, "{}:", SEGMENT_REGISTER_ENCODINGS[segment_field as usize])?;
+        }
+
+        match self.expression {
+            None => { return write!(f, "[{}]", self.displacement); },
+            Some(expression_index) => {
+                let expression: &str = REG_EXPRESSION_ENCODINGS[expression_index as usize];
+                let displacement: i16 = self.displacement as i16;
+                if displacement > 0 {
+                    return write!(f, "[{} + {}]", expression, displacement);
+                } else if displacement < 0 {
+                    return write!(f, "[{} - {}]", expression, displacement.unsigned_abs());
+                } else {
+                    return write!(f, "[{}]", expression);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Register8(field_index) => { return write!(f, "{}", REG_FIELD_ENCODINGS_8_BIT[*field_index as usize]); },
+            Operand::Register16(field_index) => { return write!(f, "{}", REG_FIELD_ENCODINGS_16_BIT[*field_index as usize]); },
+            Operand::SegmentRegister(field_index) => { return write!(f, "{}", SEGMENT_REGISTER_ENCODINGS[*field_index as usize]); },
+            Operand::Memory(memory_operand) => { return write!(f, "{}", memory_operand); },
+            Operand::Immediate(immediate) => { return write!(f, "{}", immediate); },
+            Operand::Relative(offset) => { return write!(f, "{}", offset); },
+            Operand::FarAddress { segment, offset } => { return write!(f, "{}:{}", segment, offset); }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub const PORT_COUNT: u32 = 1 << 16;
+
+// Anything that can be attached to the I/O port space. Ports are relative to the start of the
+// range the device is mapped at. Reads take &mut self since reading a port often has side effects.
+pub trait PortDevice {
+    fn read_byte(&mut self, port: u16) -> u8;
+    fn write_byte(&mut self, port: u16, byte: u8);
+}
+
+impl<T: PortDevice> PortDevice for Rc<RefCell<T>> {
+    fn read_byte(&mut self, port: u16) -> u8 {
+        return self.borrow_mut().read_byte(port);
+    }
+
+    fn write_byte(&mut self, port: u16, byte: u8) {
+        self.borrow_mut().write_byte(port, byte);
+    }
+}
+
+struct MappedPorts {
+    start: u16,
+    length: u32,
+    device: Box<dyn PortDevice>
+}
+
+impl MappedPorts {
+    fn contains(&self, port: u16) -> bool {
+        return port >= self.start && ((port - self.start) as u32) < self.length;
+    }
+}
+
+// The I/O port space IN and OUT go through. Nothing is attached by default, reads from unmapped
+// ports float high and writes to them are dropped.
+pub struct Ports {
+    ranges: Vec<MappedPorts>
+}
+
+impl Default for Ports {
+    fn default() -> Self {
+        return Ports::new();
+    }
+}
+
+impl Ports {
+    pub fn new() -> Self {
+        return Ports { ranges: Vec::new() };
+    }
+
+    // Later mappings take precedence where ranges overlap
+    pub fn map(&mut self, start: u16, length: u32, device: Box<dyn PortDevice>) {
+        assert!(start as u32 + length <= PORT_COUNT, "mapped range must fit in the port space");
+        self.ranges.push(MappedPorts { start, length, device });
+    }
+}
+
+impl PortDevice for Ports {
+    fn read_byte(&mut self, port: u16) -> u8 {
+        match self.ranges.iter_mut().rev().find(|range| range.contains(port)) {
+            Some(range) => { return range.device.read_byte(port - range.start); },
+            None => { return 0xFF; }
+        }
+    }
+
+    fn write_byte(&mut self, port: u16, byte: u8) {
+        if let Some(range) = self.ranges.iter_mut().rev().find(|range| range.contains(port)) {
+            range.device.write_byte(port - range.start, byte);
+        }
+    }
+}
+
+// A word access is a byte access to the port followed by one to the next port
+pub fn input_word(ports: &mut Ports, port: u16) -> u16 {
+    let word_low: u8 = ports.read_byte(port);
+    let word_high: u8 = ports.read_byte(port.wrapping_add(1));
+
+    return ((word_high as u16) << 8) + (word_low as u16);
+}
+
+pub fn output_word(ports: &mut Ports, port: u16, word: u16) {
+    ports.write_byte(port, (word & 0x00FF) as u8);
+    ports.write_byte(port.wrapping_add(1), ((word & 0xFF00) >> 8) as u8);
+}
@@ -0,0 +1,53 @@
+use crate::registers::*;
+use crate::instruction::*;
+
+pub fn execute_flag_control(registers: &mut Registers, operation: Operation) {
+    match operation {
+        Operation::Clc => { registers.flags &= !CF_FLAG_BIT; },
+        Operation::Stc => { registers.flags |= CF_FLAG_BIT; },
+        Operation::Cmc => { registers.flags ^= CF_FLAG_BIT; },
+        Operation::Cld => { registers.flags &= !DF_FLAG_BIT; },
+        Operation::Std => { registers.flags |= DF_FLAG_BIT; },
+        Operation::Cli => { registers.flags &= !IF_FLAG_BIT; },
+        Operation::Sti => { registers.flags |= IF_FLAG_BIT; },
+        _ => {
+            unreachable!("{:?} is not a flag operation", operation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+
+    #[test]
+    fn test_flag_control() {
+        let machine_code: &[u8] = &[
+            0xF9,       // stc
+            0xFD,       // std
+            0xFB,       // sti
+            0xF5,       // cmc
+            0xF5,       // cmc
+            0xFC,       // cld
+            0xFD,       // std
+            0xFA        // cli
+        ];
+
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+        machine.registers_mut().flags = ZF_FLAG_BIT;
+
+        machine.step().expect("Failed to step");
+        machine.step().expect("Failed to step");
+        machine.step().expect("Failed to step");
+        assert_eq!(machine.registers().flags, CF_FLAG_BIT | ZF_FLAG_BIT | IF_FLAG_BIT | DF_FLAG_BIT);
+
+        machine.step().expect("Failed to step");
+        assert_eq!(machine.registers().flags & CF_FLAG_BIT, 0);
+
+        machine.run_until(machine.program_end());
+        assert_eq!(machine.registers().flags, CF_FLAG_BIT | ZF_FLAG_BIT | DF_FLAG_BIT);
+        assert_eq!(flags_string(machine.registers().flags), "CZD");
+    }
+}
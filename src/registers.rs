@@ -1,3 +1,7 @@
+use crate::memory::*;
+
+use std::fmt;
+
 #[derive(Debug, Default)]
 pub struct Registers {
     pub ax: u16,
@@ -9,11 +13,34 @@ pub struct Registers {
     pub si: u16,
     pub di: u16,
     pub ip: u16,
-    pub flags: u16
+    pub flags: u16,
+    pub es: u16,
+    pub cs: u16,
+    pub ss: u16,
+    pub ds: u16
 }
 
-pub const ZF_FLAG_BIT: u16 = 0x0040;
-pub const SF_FLAG_BIT: u16 = 0x0080;
+pub const CF_FLAG_BIT: u16 = 0x0001;    // Carry
+pub const PF_FLAG_BIT: u16 = 0x0004;    // Parity, set when the low byte has an even number of 1 bits
+pub const AF_FLAG_BIT: u16 = 0x0010;    // Auxiliary carry out of the low nibble
+pub const ZF_FLAG_BIT: u16 = 0x0040;    // Zero
+pub const SF_FLAG_BIT: u16 = 0x0080;    // Sign
+pub const TF_FLAG_BIT: u16 = 0x0100;    // Trap
+pub const IF_FLAG_BIT: u16 = 0x0200;    // Interrupt enable
+pub const DF_FLAG_BIT: u16 = 0x0400;    // Direction
+pub const OF_FLAG_BIT: u16 = 0x0800;    // Overflow
+
+// In the order they're shown in a register dump
+pub const FLAG_NAMES: &[(u16, char)] = &[
+    (CF_FLAG_BIT, 'C'), (PF_FLAG_BIT, 'P'), (AF_FLAG_BIT, 'A'), (ZF_FLAG_BIT, 'Z'), (SF_FLAG_BIT, 'S'),
+    (TF_FLAG_BIT, 'T'), (IF_FLAG_BIT, 'I'), (DF_FLAG_BIT, 'D'), (OF_FLAG_BIT, 'O')
+];
+
+// Every flag bit which means something; the others can't be changed
+pub const DEFINED_FLAG_BITS: u16 = CF_FLAG_BIT | PF_FLAG_BIT | AF_FLAG_BIT | ZF_FLAG_BIT | SF_FLAG_BIT |
+                                   TF_FLAG_BIT | IF_FLAG_BIT | DF_FLAG_BIT | OF_FLAG_BIT;
+// The 8086 always reads bit 1 and the top four bits as set
+pub const FIXED_FLAG_BITS: u16 = 0xF002;
 
 pub fn set_low_byte(word: u16, byte: u8) -> u16 {
     return (word & 0xFF00) + byte as u16;
@@ -31,14 +58,27 @@ pub fn get_high_byte(word: u16) -> u8 {
     return ((word & 0xFF00) >> 8) as u8;
 }
 
-pub const REG_FIELD_ENCODINGS_8_BIT: &'static [&str] = &[
+pub const REG_FIELD_ENCODINGS_8_BIT: &[&str] = &[
     "al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"
 ];
 
-pub const REG_FIELD_ENCODINGS_16_BIT: &'static [&str] = &[
+pub const REG_FIELD_ENCODINGS_16_BIT: &[&str] = &[
     "ax", "cx", "dx", "bx", "sp", "bp", "si", "di"
 ];
 
+pub const SEGMENT_REGISTER_ENCODINGS: &[&str] = &[
+    "es", "cs", "ss", "ds"
+];
+
+// Registers some instructions use implicitly
+pub const CL_FIELD: u8 = 1;
+pub const DX_FIELD: u8 = 2;
+
+pub const ES_SEGMENT_FIELD: u8 = 0;
+pub const CS_SEGMENT_FIELD: u8 = 1;
+pub const SS_SEGMENT_FIELD: u8 = 2;
+pub const DS_SEGMENT_FIELD: u8 = 3;
+
 pub fn set_8_bit_register(registers: &mut Registers, field_index: u8, value: u8) {
     match field_index {
         0 => { registers.ax = set_low_byte(registers.ax, value); },
@@ -50,7 +90,7 @@ pub fn set_8_bit_register(registers: &mut Registers, field_index: u8, value: u8)
         6 => { registers.dx = set_high_byte(registers.dx, value); },
         7 => { registers.bx = set_high_byte(registers.bx, value); },
         _  => {
-            debug_assert!(false);
+            unreachable!("register field is only three bits");
         }
     }    
 }
@@ -66,7 +106,7 @@ pub fn set_16_bit_register(registers: &mut Registers, field_index: u8, value: u1
         6 => { registers.si = value; },
         7 => { registers.di = value; },
         _  => {
-            debug_assert!(false);
+            unreachable!("register field is only three bits");
         }
     }
 }
@@ -82,8 +122,7 @@ pub fn get_8_bit_register(registers: &Registers, field_index: u8) -> u8 {
         6 => { return get_high_byte(registers.dx); },
         7 => { return get_high_byte(registers.bx); },
         _  => {
-            debug_assert!(false);
-            return 0;
+            unreachable!("register field is only three bits");
         }
     }    
 }
@@ -99,13 +138,36 @@ pub fn get_16_bit_register(registers: &Registers, field_index: u8) -> u16 {
         6 => { return registers.si; },
         7 => { return registers.di; },
         _  => {
-            debug_assert!(false);
-            return 0;
+            unreachable!("register field is only three bits");
+        }
+    }
+}
+
+pub fn set_segment_register(registers: &mut Registers, field_index: u8, value: u16) {
+    match field_index {
+        0 => { registers.es = value; },
+        1 => { registers.cs = value; },
+        2 => { registers.ss = value; },
+        3 => { registers.ds = value; },
+        _  => {
+            unreachable!("segment register field is only two bits");
+        }
+    }
+}
+
+pub fn get_segment_register(registers: &Registers, field_index: u8) -> u16 {
+    match field_index {
+        0 => { return registers.es; },
+        1 => { return registers.cs; },
+        2 => { return registers.ss; },
+        3 => { return registers.ds; },
+        _  => {
+            unreachable!("segment register field is only two bits");
         }
     }
 }
 
-pub const REG_EXPRESSION_ENCODINGS: &'static [&str] = &[
+pub const REG_EXPRESSION_ENCODINGS: &[&str] = &[
     "bx + si",
     "bx + di",
     "bp + si",
@@ -116,19 +178,51 @@ pub const REG_EXPRESSION_ENCODINGS: &'static [&str] = &[
     "bx"
 ];
 
-pub fn calculate_reg_expression(registers: &Registers, expression_index: u8) -> u16 {
+// Expressions based on bp address the stack segment, everything else the data segment
+pub fn reg_expression_default_segment(expression_index: u8) -> u8 {
     match expression_index {
-        0 => { return registers.bx.wrapping_add(registers.si); },
-        1 => { return registers.bx.wrapping_add(registers.di); },
-        2 => { return registers.bp.wrapping_add(registers.si); },
-        3 => { return registers.bp.wrapping_add(registers.di); },
-        4 => { return registers.si; },
-        5 => { return registers.di; },
-        6 => { return registers.bp; },
-        7 => { return registers.bx; },
+        2 | 3 | 6 => { return SS_SEGMENT_FIELD; },
+        _ => { return DS_SEGMENT_FIELD; }
+    }
+}
+
+pub fn calculate_reg_expression(registers: &Registers, expression_index: u8) -> SegmentedAddress {
+    let offset: u16 = match expression_index {
+        0 => registers.bx.wrapping_add(registers.si),
+        1 => registers.bx.wrapping_add(registers.di),
+        2 => registers.bp.wrapping_add(registers.si),
+        3 => registers.bp.wrapping_add(registers.di),
+        4 => registers.si,
+        5 => registers.di,
+        6 => registers.bp,
+        7 => registers.bx,
         _ => {
-            debug_assert!(false);
-            return 0;
+            unreachable!("r/m field is only three bits");
         }
+    };
+
+    let segment: u16 = get_segment_register(registers, reg_expression_default_segment(expression_index));
+
+    return SegmentedAddress::new(segment, offset);
+}
+
+// One letter for each flag which is set, e.g. CZSD
+pub fn flags_string(flags: u16) -> String {
+    return FLAG_NAMES.iter().filter(|(flag_bit, _)| flags & flag_bit != 0).map(|(_, name)| name).collect();
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let named_registers: &[(&str, u16)] = &[
+            ("ax", self.ax), ("bx", self.bx), ("cx", self.cx), ("dx", self.dx),
+            ("sp", self.sp), ("bp", self.bp), ("si", self.si), ("di", self.di),
+            ("es", self.es), ("cs", self.cs), ("ss", self.ss), ("ds", self.ds),
+            ("ip", self.ip)
+        ];
+        for (name, value) in named_registers {
+            writeln!(f, "{}: 0x{:04X} ({})", name, value, value)?;
+        }
+
+        return write!(f, "flags: {}", flags_string(self.flags));
     }
 }
@@ -0,0 +1,205 @@
+use crate::registers::*;
+use crate::memory::*;
+use crate::instruction::*;
+use crate::operand::*;
+use crate::arithmetic::*;
+
+// [si] and [di] as r/m expressions
+const SI_EXPRESSION: u8 = 4;
+const DI_EXPRESSION: u8 = 5;
+
+// DS:SI unless there's a segment prefix
+fn source_operand(instruction: &Instruction) -> Operand {
+    let segment_field: u8 = instruction.prefixes.segment_or(DS_SEGMENT_FIELD);
+    return Operand::Memory(MemoryOperand { segment_override: Some(segment_field), ..MemoryOperand::indirect(SI_EXPRESSION, 0) });
+}
+
+// Always ES:DI, a segment prefix can't change it
+fn destination_operand() -> Operand {
+    return Operand::Memory(MemoryOperand { segment_override: Some(ES_SEGMENT_FIELD), ..MemoryOperand::indirect(DI_EXPRESSION, 0) });
+}
+
+// Forwards when DF is clear and backwards when it is set
+fn advance(index: u16, registers: &Registers, width: Width) -> u16 {
+    let step: u16 = match width {
+        Width::Byte => 1,
+        Width::Word => 2
+    };
+
+    if registers.flags & DF_FLAG_BIT == 0 {
+        return index.wrapping_add(step);
+    } else {
+        return index.wrapping_sub(step);
+    }
+}
+
+fn string_op_once(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let width: Width = instruction.width;
+    let accumulator: Operand = Operand::register(0, width);
+
+    match instruction.operation {
+        Operation::Movs => {
+            let value: u16 = read_operand(registers, memory, source_operand(instruction), width);
+            write_operand(registers, memory, destination_operand(), width, value);
+            registers.si = advance(registers.si, registers, width);
+            registers.di = advance(registers.di, registers, width);
+        },
+        Operation::Cmps => {
+            let x: u16 = read_operand(registers, memory, source_operand(instruction), width);
+            let y: u16 = read_operand(registers, memory, destination_operand(), width);
+            arithmetic_op(Operation::Cmp, x, y, width, &mut registers.flags);
+            registers.si = advance(registers.si, registers, width);
+            registers.di = advance(registers.di, registers, width);
+        },
+        Operation::Scas => {
+            let x: u16 = read_operand(registers, memory, accumulator, width);
+            let y: u16 = read_operand(registers, memory, destination_operand(), width);
+            arithmetic_op(Operation::Cmp, x, y, width, &mut registers.flags);
+            registers.di = advance(registers.di, registers, width);
+        },
+        Operation::Lods => {
+            let value: u16 = read_operand(registers, memory, source_operand(instruction), width);
+            write_operand(registers, memory, accumulator, width, value);
+            registers.si = advance(registers.si, registers, width);
+        },
+        Operation::Stos => {
+            let value: u16 = read_operand(registers, memory, accumulator, width);
+            write_operand(registers, memory, destination_operand(), width, value);
+            registers.di = advance(registers.di, registers, width);
+        },
+        _ => {
+            unreachable!("{:?} is not a string instruction", instruction.operation);
+        }
+    }
+}
+
+// With a repeat prefix the whole loop runs as one instruction. It stops when CX runs out, and for
+// cmps and scas also when ZF no longer matches the prefix.
+pub fn execute_string(registers: &mut Registers, memory: &mut Memory, instruction: &Instruction) {
+    let repeat: Repeat = match instruction.prefixes.repeat {
+        Some(repeat) => repeat,
+        None => {
+            string_op_once(registers, memory, instruction);
+            return;
+        }
+    };
+
+    let checks_zero: bool = matches!(instruction.operation, Operation::Cmps | Operation::Scas);
+    while registers.cx != 0 {
+        string_op_once(registers, memory, instruction);
+        registers.cx = registers.cx.wrapping_sub(1);
+
+        let zero: bool = registers.flags & ZF_FLAG_BIT != 0;
+        if checks_zero && zero != (repeat == Repeat::Rep) {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::Machine;
+
+    fn string_machine(machine_code: &[u8]) -> Machine {
+        let mut machine = Machine::new();
+        machine.load_program(machine_code);
+        machine.registers_mut().ds = 0x1000;
+        machine.registers_mut().es = 0x2000;
+
+        return machine;
+    }
+
+    #[test]
+    fn test_rep_movs() {
+        let mut machine: Machine = string_machine(&[0xF3, 0xA4]);  // rep movsb
+        machine.load(0x10010, b"hello");
+        *machine.registers_mut() = Registers { si: 0x0010, di: 0x0020, cx: 5, ..*machine.registers() };
+        machine.step().expect("Failed to step");
+
+        let copied: Vec<u8> = (0..5).map(|index: u32| machine.memory().read_byte(0x20020 + index)).collect();
+        assert_eq!(copied, b"hello");
+        assert_eq!((machine.registers().si, machine.registers().di, machine.registers().cx), (0x0015, 0x0025, 0));
+        assert_eq!(machine.instruction_count(), 1);
+
+        // Backwards a word at a time with DF set
+        let mut machine: Machine = string_machine(&[0xF3, 0xA5]);  // rep movsw
+        machine.load(0x10010, &[0x11, 0x22, 0x33, 0x44]);
+        *machine.registers_mut() = Registers { si: 0x0012, di: 0x0022, cx: 2, flags: DF_FLAG_BIT, ..*machine.registers() };
+        machine.step().expect("Failed to step");
+
+        assert_eq!(load_word(machine.memory(), SegmentedAddress::new(0x2000, 0x0020)), 0x2211);
+        assert_eq!(load_word(machine.memory(), SegmentedAddress::new(0x2000, 0x0022)), 0x4433);
+        assert_eq!((machine.registers().si, machine.registers().di, machine.registers().cx), (0x000E, 0x001E, 0));
+    }
+
+    #[test]
+    fn test_rep_with_zero_count() {
+        let mut machine: Machine = string_machine(&[0xF3, 0xAA]);  // rep stosb
+        *machine.registers_mut() = Registers { ax: 0x00FF, di: 0x0020, cx: 0, ..*machine.registers() };
+        machine.step().expect("Failed to step");
+
+        assert_eq!(machine.memory().read_byte(0x20020), 0);
+        assert_eq!(machine.registers().di, 0x0020);
+    }
+
+    #[test]
+    fn test_stos_lods() {
+        let machine_code: &[u8] = &[
+            0xF3, 0xAB,                 // rep stosw
+            0xAC,                       // lodsb
+            0x26, 0xAD                  // es lodsw
+        ];
+
+        let mut machine: Machine = string_machine(machine_code);
+        machine.load(0x10000, &[0x7F]);
+        *machine.registers_mut() = Registers { ax: 0xABCD, si: 0x0000, di: 0x0000, cx: 3, ..*machine.registers() };
+        machine.run_until(machine.program_end());
+
+        assert_eq!(load_word(machine.memory(), SegmentedAddress::new(0x2000, 0x0004)), 0xABCD);
+        assert_eq!(machine.memory().read_byte(0x20006), 0);
+        assert_eq!(machine.registers().di, 0x0006);
+
+        // The override applies to the source of the second lods which reads es:1
+        assert_eq!(machine.registers().ax, 0xCDAB);
+        assert_eq!(machine.registers().si, 0x0003);
+    }
+
+    #[test]
+    fn test_repne_scas() {
+        let mut machine: Machine = string_machine(&[0xF2, 0xAE]);  // repne scasb
+        machine.load(0x20000, b"find the x here");
+        *machine.registers_mut() = Registers { ax: b'x' as u16, di: 0x0000, cx: 15, ..*machine.registers() };
+        machine.step().expect("Failed to step");
+
+        // di ends up just past the match
+        assert_eq!(machine.registers().di, 10);
+        assert_eq!(machine.registers().cx, 5);
+        assert_ne!(machine.registers().flags & ZF_FLAG_BIT, 0);
+    }
+
+    #[test]
+    fn test_repe_cmps() {
+        let mut machine: Machine = string_machine(&[0xF3, 0xA6]);  // repe cmpsb
+        machine.load(0x10000, b"abcdef");
+        machine.load(0x20000, b"abcxef");
+        *machine.registers_mut() = Registers { si: 0x0000, di: 0x0000, cx: 6, ..*machine.registers() };
+        machine.step().expect("Failed to step");
+
+        // Stops after comparing the first mismatch, d - x
+        assert_eq!((machine.registers().si, machine.registers().di, machine.registers().cx), (4, 4, 2));
+        assert_eq!(machine.registers().flags & ZF_FLAG_BIT, 0);
+        assert_ne!(machine.registers().flags & CF_FLAG_BIT, 0);
+
+        // Runs to the end when everything matches
+        let mut machine: Machine = string_machine(&[0xF3, 0xA7]);  // repe cmpsw
+        machine.load(0x10000, b"abcd");
+        machine.load(0x20000, b"abcd");
+        *machine.registers_mut() = Registers { si: 0x0000, di: 0x0000, cx: 2, ..*machine.registers() };
+        machine.step().expect("Failed to step");
+
+        assert_eq!(machine.registers().cx, 0);
+        assert_ne!(machine.registers().flags & ZF_FLAG_BIT, 0);
+    }
+}
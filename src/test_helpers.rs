@@ -0,0 +1,12 @@
+use crate::registers::Registers;
+use crate::Machine;
+
+// Loads the machine code at 0000:0000, swaps in the given registers and runs to the end of the code
+pub fn run_machine_code(machine_code: &[u8], registers: Registers) -> Machine {
+    let mut machine = Machine::new();
+    machine.load_program(machine_code);
+    *machine.registers_mut() = registers;
+    machine.run_until(machine.program_end());
+
+    return machine;
+}
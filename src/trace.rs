@@ -0,0 +1,109 @@
+use crate::instruction::*;
+
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::rc::Rc;
+
+// Receives every instruction the machine executes, in execution order. A failure stops the run.
+pub trait TraceSink {
+    fn trace(&mut self, instruction: &Instruction) -> io::Result<()>;
+
+    // Called whenever a run stops so buffered output isn't lost if the process then exits
+    fn flush(&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+// Written first by the text sinks so their output assembles as it is
+const NASM_HEADER: &str = "bits 16";
+
+// Prints each instruction as NASM text
+#[derive(Debug)]
+pub struct StdoutTrace {
+    stdout: io::Stdout
+}
+
+impl StdoutTrace {
+    pub fn new() -> io::Result<Self> {
+        let mut stdout = io::stdout();
+        writeln!(stdout, "{}", NASM_HEADER)?;
+
+        return Ok(StdoutTrace { stdout });
+    }
+}
+
+impl TraceSink for StdoutTrace {
+    fn trace(&mut self, instruction: &Instruction) -> io::Result<()> {
+        return writeln!(self.stdout, "{}", instruction);
+    }
+}
+
+// Discards everything, for when only the final machine state matters
+#[derive(Debug, Default)]
+pub struct NullTrace;
+
+impl TraceSink for NullTrace {
+    fn trace(&mut self, _instruction: &Instruction) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+// Writes each instruction as NASM text to a file which can be fed straight back into the assembler
+pub struct FileTrace {
+    writer: io::BufWriter<fs::File>
+}
+
+impl FileTrace {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut writer = io::BufWriter::new(fs::File::create(path)?);
+        writeln!(writer, "{}", NASM_HEADER)?;
+
+        return Ok(FileTrace { writer });
+    }
+}
+
+impl TraceSink for FileTrace {
+    fn trace(&mut self, instruction: &Instruction) -> io::Result<()> {
+        return writeln!(self.writer, "{}", instruction);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.writer.flush();
+    }
+}
+
+// Keeps the executed instructions in memory. Clones share the same storage so a clone can be
+// handed to the machine while the original is used to inspect what ran.
+#[derive(Debug, Default, Clone)]
+pub struct CollectorTrace {
+    instructions: Rc<RefCell<Vec<Instruction>>>
+}
+
+impl CollectorTrace {
+    pub fn new() -> Self {
+        return CollectorTrace::default();
+    }
+
+    pub fn instructions(&self) -> Vec<Instruction> {
+        return self.instructions.borrow().clone();
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        return self.instructions.borrow().iter().map(|instruction| instruction.to_string()).collect();
+    }
+
+    pub fn clear(&self) {
+        self.instructions.borrow_mut().clear();
+    }
+}
+
+impl TraceSink for CollectorTrace {
+    fn trace(&mut self, instruction: &Instruction) -> io::Result<()> {
+        self.instructions.borrow_mut().push(*instruction);
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,38 @@
+use emulator_8086::{Machine, StopReason};
+use emulator_8086::registers::Registers;
+use emulator_8086::memory::Memory;
+use emulator_8086::interrupt::{InterruptHandler, DIVIDE_ERROR_VECTOR};
+
+// Stands in for a DOS style service which returns a value in ax
+struct Service {
+    calls: u32
+}
+
+impl InterruptHandler for Service {
+    fn handle(&mut self, registers: &mut Registers, _memory: &mut Memory, _vector: u8) {
+        self.calls += 1;
+        registers.ax = 0x1234;
+    }
+}
+
+#[test]
+fn test_hooks_through_public_api() {
+    let machine_code: &[u8] = &[
+        0xCD, 0x21,             // int 33
+        0x89, 0xC3,             // mov bx, ax
+        0xF6, 0xF1,             // div cl
+        0xF4                    // hlt
+    ];
+
+    let mut machine = Machine::new();
+    machine.interrupt_hooks_mut().hook(0x21, Box::new(Service { calls: 0 }));
+    machine.interrupt_hooks_mut().hook(DIVIDE_ERROR_VECTOR, Box::new(|registers: &mut Registers, _memory: &mut Memory, _vector: u8| {
+        registers.dx = 0xDEAD;
+    }));
+    machine.load_program_at(0x0100, machine_code);
+
+    assert_eq!(machine.run(), StopReason::Halted);
+    assert_eq!(machine.registers().bx, 0x1234);
+    assert_eq!(machine.registers().dx, 0xDEAD);
+    assert!(machine.interrupt_hooks_mut().is_hooked(0x21));
+}